@@ -7,9 +7,7 @@ fn main() {
 		println!("\tAxes: {}", js.num_axes());
 		println!("\tButtons: {}", js.num_buttons());
 	}
-	loop {
-		for event in backend.poll() {
-			println!("{:?}", event);
-		}
+	while let Some(event) = backend.wait() {
+		println!("{:?}", event);
 	}
 }
@@ -0,0 +1,208 @@
+//! Calibration of axes by the Linux joystick driver, which corrects the raw
+//! values of axes before they are reported.
+//!
+//! Corrections are read and written through `linux::NativeJoystick` and can
+//! be saved in the format `jscal -s` takes, like `2,1,0,127,127,4227201,4194176,...`,
+//! which is the number of axes followed by the type, precision and
+//! coefficients of each axis. `jscal -p` prints this as part of the command
+//! that restores it, which can be read too.
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// The type of correction that passes raw values through
+pub const JS_CORR_NONE: u16 = 0;
+/// The type of correction that maps raw values along a broken line
+pub const JS_CORR_BROKEN: u16 = 1;
+
+/// How the driver corrects the raw values of an axis.
+///
+/// ``` rust
+/// use reminisce::calibration::Correction;
+/// let correction = Correction::from_range(0, 128, 255);
+/// assert_eq!(correction.apply(0), -32767);
+/// assert_eq!(correction.apply(128), 0);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Correction {
+	/// Pass raw values through unchanged
+	None,
+	/// Map raw values onto the range `-32767` to `32767` along a line either
+	/// side of a flat centre, like `jscal` does
+	Broken {
+		/// The amount of noise to ignore
+		precision: i16,
+		/// The lowest raw value treated as centred
+		center_min: i32,
+		/// The highest raw value treated as centred
+		center_max: i32,
+		/// The slope below the centre, in units of `1 / 16384`
+		negative: i32,
+		/// The slope above the centre, in units of `1 / 16384`
+		positive: i32
+	}
+}
+
+impl Correction {
+	/// Compute the correction that maps the range observed from an axis onto
+	/// `-32767` to `32767`, with `center` being the value it rests at.
+	pub fn from_range(min: i32, center: i32, max: i32) -> Correction {
+		Correction::Broken {
+			precision: 0,
+			center_min: center,
+			center_max: center,
+			negative: (32767 << 14) / (center - min).max(1),
+			positive: (32767 << 14) / (max - center).max(1)
+		}
+	}
+	/// Correct a raw value the way the driver does
+	pub fn apply(&self, value: i32) -> i32 {
+		match *self {
+			Correction::None => value,
+			Correction::Broken { center_min, center_max, negative, positive, .. } => {
+				let value = if value > center_min {
+					if value < center_max {
+						0
+					} else {
+						((positive as i64 * (value - center_max) as i64) >> 14) as i32
+					}
+				} else {
+					((negative as i64 * (value - center_min) as i64) >> 14) as i32
+				};
+				value.max(-32767).min(32767)
+			}
+		}
+	}
+	/// Convert this into the driver's `js_corr` structure
+	pub(crate) fn to_raw(&self) -> JsCorr {
+		match *self {
+			Correction::None => JsCorr { coef: [0; 8], prec: 0, _type: JS_CORR_NONE },
+			Correction::Broken { precision, center_min, center_max, negative, positive } => JsCorr {
+				coef: [center_min, center_max, negative, positive, 0, 0, 0, 0],
+				prec: precision,
+				_type: JS_CORR_BROKEN
+			}
+		}
+	}
+	/// Convert the driver's `js_corr` structure into a correction
+	pub(crate) fn from_raw(raw: &JsCorr) -> Result<Correction, Error> {
+		match raw._type {
+			JS_CORR_NONE => Ok(Correction::None),
+			JS_CORR_BROKEN => Ok(Correction::Broken {
+				precision: raw.prec,
+				center_min: raw.coef[0],
+				center_max: raw.coef[1],
+				negative: raw.coef[2],
+				positive: raw.coef[3]
+			}),
+			_ => Err(Error::new(ErrorKind::InvalidData, "unknown correction type"))
+		}
+	}
+}
+
+/// The driver's `js_corr` structure
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct JsCorr {
+	pub coef: [i32; 8],
+	pub prec: i16,
+	pub _type: u16
+}
+
+/// Write the corrections of each axis in the format `jscal -s` takes
+pub fn to_jscal(corrections: &[Correction]) -> String {
+	let mut fields = vec![corrections.len().to_string()];
+	for correction in corrections {
+		match *correction {
+			Correction::None => fields.extend(vec![JS_CORR_NONE.to_string(), "0".to_owned()]),
+			Correction::Broken { precision, center_min, center_max, negative, positive } => {
+				let numbers = vec![JS_CORR_BROKEN as i32, precision as i32, center_min, center_max, negative, positive];
+				fields.extend(numbers.into_iter().map(|n| n.to_string()));
+			}
+		}
+	}
+	fields.join(",")
+}
+
+/// Read the corrections of each axis from the format `jscal -s` takes, or
+/// from the whole `jscal -s <values> /dev/input/jsN` command `jscal -p`
+/// prints.
+///
+/// ``` rust
+/// use reminisce::calibration::{self, Correction};
+/// let corrections = vec![Correction::from_range(0, 128, 255), Correction::None];
+/// let text = calibration::to_jscal(&corrections);
+/// assert_eq!(calibration::from_jscal(&text).unwrap(), corrections);
+/// assert!(calibration::from_jscal("2147483647,0,0").is_err());
+/// let command = format!("jscal -s {} /dev/input/js0", text);
+/// assert_eq!(calibration::from_jscal(&command).unwrap(), corrections);
+/// assert!(calibration::from_jscal(&(text.clone() + ",0")).is_err());
+/// assert!(calibration::from_jscal(&(text + " /dev/input/js0 1")).is_err());
+/// ```
+pub fn from_jscal(text: &str) -> Result<Vec<Correction>, Error> {
+	let invalid = || Error::new(ErrorKind::InvalidData, "invalid calibration");
+	let mut words = text.split_whitespace().peekable();
+	// Skip the command `jscal -p` prints around the values
+	if words.peek() == Some(&"jscal") {
+		words.next();
+		if words.next() != Some("-s") {
+			return Err(invalid())
+		}
+	}
+	let values = try!(words.next().ok_or_else(invalid));
+	// The values may be followed by the device they are for, but nothing more
+	words.next();
+	if words.next().is_some() {
+		return Err(invalid())
+	}
+	let mut numbers = Vec::new();
+	for field in values.split(',') {
+		numbers.push(try!(field.parse::<i32>().map_err(|_| invalid())));
+	}
+	let mut numbers = numbers.into_iter();
+	let count = try!(numbers.next().ok_or_else(invalid));
+	if count < 0 {
+		return Err(invalid())
+	}
+	// The count comes from the file, so it isn't trusted to size anything
+	let mut corrections = Vec::new();
+	for _ in 0..count {
+		let kind = try!(numbers.next().ok_or_else(invalid));
+		let precision = try!(numbers.next().ok_or_else(invalid)) as i16;
+		let correction = match kind as u16 {
+			JS_CORR_NONE => Correction::None,
+			JS_CORR_BROKEN => {
+				let mut coef = [0; 4];
+				for c in coef.iter_mut() {
+					*c = try!(numbers.next().ok_or_else(invalid));
+				}
+				Correction::Broken {
+					precision: precision,
+					center_min: coef[0],
+					center_max: coef[1],
+					negative: coef[2],
+					positive: coef[3]
+				}
+			},
+			_ => return Err(invalid())
+		};
+		corrections.push(correction);
+	}
+	if numbers.next().is_some() {
+		return Err(invalid())
+	}
+	Ok(corrections)
+}
+
+/// Save the corrections of each axis to a file
+pub fn save<P>(path: P, corrections: &[Correction]) -> Result<(), Error> where P: AsRef<Path> {
+	let mut file = try!(File::create(path));
+	writeln!(file, "{}", to_jscal(corrections))
+}
+
+/// Load the corrections of each axis from a file written by `save`
+pub fn load<P>(path: P) -> Result<Vec<Correction>, Error> where P: AsRef<Path> {
+	let mut text = String::new();
+	try!(try!(File::open(path)).read_to_string(&mut text));
+	from_jscal(&text)
+}
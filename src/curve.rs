@@ -0,0 +1,220 @@
+//! Response curves that shape how far an axis reports being pushed, for
+//! finer control near the centre of a stick.
+use std::collections::HashMap;
+use deadzone::Axes;
+use {Axis, Event, JoystickIndex};
+
+/// The shape of a response curve, which maps the distance an axis is pushed
+/// from `0` to `1` onto the distance it reports.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+	/// Report the distance unchanged
+	Linear,
+	/// Curve the distance along `(e^(kx) - 1) / (e^k - 1)`, which is linear
+	/// when `k` is `0` and grows steeper as `k` grows
+	Exponential(f32),
+	/// Raise the distance to the power given, so powers above `1` give finer
+	/// control near the centre
+	Power(f32),
+	/// Interpolate linearly between points, each being a distance pushed and
+	/// the distance to report, sorted by the distance pushed.
+	///
+	/// The curve is assumed to pass through `(0, 0)` and `(1, 1)` outside the
+	/// points given.
+	Table(Vec<(f32, f32)>)
+}
+
+impl Shape {
+	/// Map a distance from `0` to `1` along this shape
+	fn map(&self, distance: f32) -> f32 {
+		match *self {
+			Shape::Linear => distance,
+			Shape::Exponential(k) if k == 0.0 => distance,
+			Shape::Exponential(k) => ((k * distance).exp() - 1.0) / (k.exp() - 1.0),
+			Shape::Power(power) => distance.powf(power),
+			Shape::Table(ref points) => {
+				let mut last = (0.0, 0.0);
+				for &(x, y) in points.iter().chain(Some(&(1.0, 1.0))) {
+					if distance <= x {
+						return if x <= last.0 {
+							y
+						} else {
+							last.1 + (y - last.1) * (distance - last.0) / (x - last.0)
+						}
+					}
+					last = (x, y);
+				}
+				last.1
+			}
+		}
+	}
+}
+
+/// A response curve along with a sensitivity and whether to invert the axis.
+///
+/// The curve is applied to the distance from the centre, so it is symmetric,
+/// and then the sensitivity is multiplied in and the result clamped to the
+/// range `-1` to `1`.
+///
+/// ``` rust
+/// use reminisce::curve::{Curve, Shape};
+/// let curve = Curve::new(Shape::Power(2.0)).inverted();
+/// assert_eq!(curve.apply(0.5), -0.25);
+/// assert_eq!(curve.apply(-1.0), 1.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+	pub shape: Shape,
+	/// The multiplier applied after the shape
+	pub sensitivity: f32,
+	pub inverted: bool
+}
+
+impl Curve {
+	/// Create a curve of the shape given, with a sensitivity of `1`
+	pub fn new(shape: Shape) -> Curve {
+		Curve {
+			shape: shape,
+			sensitivity: 1.0,
+			inverted: false
+		}
+	}
+	/// Multiply values by `sensitivity` after shaping them
+	pub fn with_sensitivity(self, sensitivity: f32) -> Curve {
+		Curve {
+			sensitivity: sensitivity,
+			..self
+		}
+	}
+	/// Invert the axis
+	pub fn inverted(self) -> Curve {
+		Curve {
+			inverted: !self.inverted,
+			..self
+		}
+	}
+	/// Apply this to the value of an axis
+	pub fn apply(&self, value: f32) -> f32 {
+		let distance = self.shape.map(value.abs().min(1.0)) * self.sensitivity;
+		let value = (distance * value.signum()).max(-1.0).min(1.0);
+		if self.inverted { -value } else { value }
+	}
+	/// Apply this to a stick made of a pair of axes, shaping the distance of
+	/// the stick from the centre so its direction is kept
+	pub fn apply_pair(&self, x: f32, y: f32) -> (f32, f32) {
+		let distance = (x * x + y * y).sqrt();
+		if distance == 0.0 {
+			return (0.0, 0.0)
+		}
+		let scale = self.apply(distance.min(1.0)) / distance;
+		(x * scale, y * scale)
+	}
+}
+
+/// Response curves for the axes of every joystick, which can be applied to
+/// events and to a `StatefulJoystick`.
+///
+/// Curves set for a specific joystick take priority over those set for every
+/// joystick.
+///
+/// ``` rust
+/// use reminisce::Event;
+/// use reminisce::curve::{Curve, Curves, Shape};
+///
+/// let mut curves = Curves::new();
+/// curves.set_axis(None, 1, Curve::new(Shape::Linear).with_sensitivity(2.0));
+/// assert_eq!(curves.process(Event::AxisMoved(0, 1, 0.25)), vec![Event::AxisMoved(0, 1, 0.5)]);
+///
+/// // The other axis of a stick is only reported when its value changes
+/// curves.set_pair(None, 2, 3, Curve::new(Shape::Linear));
+/// assert_eq!(curves.process(Event::AxisMoved(0, 2, 0.5)), vec![Event::AxisMoved(0, 2, 0.5)]);
+/// assert_eq!(curves.process(Event::AxisMoved(0, 2, 0.5)), vec![]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Curves {
+	curves: Vec<(Option<JoystickIndex>, Axes, Curve)>,
+	/// The last raw value of each axis
+	raw: HashMap<(JoystickIndex, Axis), f32>,
+	/// The last value of each axis that was emitted
+	emitted: HashMap<(JoystickIndex, Axis), f32>
+}
+
+impl Curves {
+	/// Create a new set of curves, which leaves every axis untouched
+	pub fn new() -> Curves {
+		Curves {
+			curves: Vec::new(),
+			raw: HashMap::new(),
+			emitted: HashMap::new()
+		}
+	}
+	/// Use the curve given for a single axis of the joystick given, or of
+	/// every joystick if none is given
+	pub fn set_axis(&mut self, joystick: Option<JoystickIndex>, axis: Axis, curve: Curve) {
+		self.set(joystick, Axes::Single(axis), curve);
+	}
+	/// Use the curve given for a stick made of the `x` and `y` axes of the
+	/// joystick given, or of every joystick if none is given
+	pub fn set_pair(&mut self, joystick: Option<JoystickIndex>, x: Axis, y: Axis, curve: Curve) {
+		self.set(joystick, Axes::Pair(x, y), curve);
+	}
+	fn set(&mut self, joystick: Option<JoystickIndex>, axes: Axes, curve: Curve) {
+		// Replace any curve that covered the same axes
+		self.curves.retain(|&(j, a, _)| j != joystick || !a.overlaps(axes));
+		self.curves.push((joystick, axes, curve));
+	}
+	/// Get the curve for an axis of a joystick and the axes it covers
+	fn find(&self, joystick: JoystickIndex, axis: Axis) -> Option<(Axes, &Curve)> {
+		let specific = self.curves.iter().find(|&&(j, a, _)| j == Some(joystick) && a.contains(axis));
+		let general = self.curves.iter().find(|&&(j, a, _)| j.is_none() && a.contains(axis));
+		specific.or(general).map(|&(_, axes, ref curve)| (axes, curve))
+	}
+	/// Apply the curve for an axis of a joystick to its value, looking up the
+	/// value of the other axis of a stick with `axis_value`
+	pub fn apply<F>(&self, joystick: JoystickIndex, axis: Axis, value: f32, axis_value: F) -> f32
+		where F: Fn(Axis) -> f32 {
+		match self.find(joystick, axis) {
+			None => value,
+			Some((Axes::Single(_), curve)) => curve.apply(value),
+			Some((Axes::Pair(x, y), curve)) if x == axis => curve.apply_pair(value, axis_value(y)).0,
+			Some((Axes::Pair(x, _), curve)) => curve.apply_pair(axis_value(x), value).1
+		}
+	}
+	/// Apply the curves to an event, returning the events to emit instead.
+	///
+	/// Moving one axis of a stick can change the value of the other, so this
+	/// can return an event for each axis. Axes that haven't changed from the
+	/// last value emitted are left out.
+	pub fn process(&mut self, event: Event) -> Vec<Event> {
+		let (index, axis, value) = match event {
+			Event::AxisMoved(index, axis, value) => (index, axis, value),
+			Event::Disconnected(index) => {
+				self.raw.retain(|&(i, _), _| i != index);
+				self.emitted.retain(|&(i, _), _| i != index);
+				return vec![event]
+			},
+			_ => return vec![event]
+		};
+		self.raw.insert((index, axis), value);
+		let axes = match self.find(index, axis) {
+			Some((Axes::Pair(x, y), _)) => vec![x, y],
+			_ => vec![axis]
+		};
+		let mut events = Vec::with_capacity(axes.len());
+		for a in axes {
+			let raw = match self.raw.get(&(index, a)) {
+				Some(&raw) => raw,
+				None => continue
+			};
+			let curved = {
+				let raw_values = &self.raw;
+				self.apply(index, a, raw, |other| raw_values.get(&(index, other)).cloned().unwrap_or(0.0))
+			};
+			// Axes start at rest, so only changes from 0 need emitting
+			if self.emitted.insert((index, a), curved).unwrap_or(0.0) != curved {
+				events.push(Event::AxisMoved(index, a, curved));
+			}
+		}
+		events
+	}
+}
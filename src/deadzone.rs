@@ -0,0 +1,225 @@
+//! Dead zones that stop axes at rest from jittering around `0` and let them
+//! reach `-1` and `1` before their physical limits.
+use std::collections::HashMap;
+use {Axis, Event, JoystickIndex};
+
+/// The shape of a dead zone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeadZoneKind {
+	/// Each axis is cut off and rescaled on its own, which snaps sticks to
+	/// the horizontal and vertical near the centre
+	Axial,
+	/// A stick is cut off by its distance from the centre, keeping its
+	/// direction but jumping in magnitude at the edge of the dead zone
+	Radial,
+	/// A stick is cut off by its distance from the centre and its magnitude
+	/// is rescaled so it rises smoothly from the edge of the dead zone
+	ScaledRadial
+}
+
+/// A dead zone around the centre of an axis or stick along with an outer
+/// dead zone near its limits.
+///
+/// ``` rust
+/// use reminisce::deadzone::DeadZone;
+/// let zone = DeadZone::scaled_radial(0.2).with_outer(0.9);
+/// assert_eq!(zone.apply(0.1, 0.1), (0.0, 0.0));
+/// assert_eq!(zone.apply(0.0, -0.95), (0.0, -1.0));
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeadZone {
+	pub kind: DeadZoneKind,
+	/// The distance from the centre below which input is ignored
+	pub inner: f32,
+	/// The distance from the centre above which input is treated as `1`
+	pub outer: f32
+}
+
+impl DeadZone {
+	/// Create a dead zone of the kind and size given, without an outer dead zone
+	pub fn new(kind: DeadZoneKind, inner: f32) -> DeadZone {
+		DeadZone {
+			kind: kind,
+			inner: inner,
+			outer: 1.0
+		}
+	}
+	/// Create an axial dead zone of the size given
+	pub fn axial(inner: f32) -> DeadZone {
+		DeadZone::new(DeadZoneKind::Axial, inner)
+	}
+	/// Create a radial dead zone of the size given
+	pub fn radial(inner: f32) -> DeadZone {
+		DeadZone::new(DeadZoneKind::Radial, inner)
+	}
+	/// Create a scaled radial dead zone of the size given
+	pub fn scaled_radial(inner: f32) -> DeadZone {
+		DeadZone::new(DeadZoneKind::ScaledRadial, inner)
+	}
+	/// Treat input further than `outer` from the centre as fully pushed
+	pub fn with_outer(self, outer: f32) -> DeadZone {
+		DeadZone {
+			outer: outer,
+			..self
+		}
+	}
+	/// Rescale a distance from the centre from between the inner and outer
+	/// dead zones into the range `0` to `1`
+	fn scale(&self, distance: f32) -> f32 {
+		if distance <= self.inner {
+			0.0
+		} else if distance >= self.outer || self.outer <= self.inner {
+			1.0
+		} else {
+			(distance - self.inner) / (self.outer - self.inner)
+		}
+	}
+	/// Apply this to a single axis, for which every kind behaves the same
+	pub fn apply_axis(&self, value: f32) -> f32 {
+		self.scale(value.abs()) * value.signum()
+	}
+	/// Apply this to a stick made of a pair of axes
+	pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+		let distance = (x * x + y * y).sqrt();
+		match self.kind {
+			DeadZoneKind::Axial => (self.apply_axis(x), self.apply_axis(y)),
+			_ if distance <= self.inner => (0.0, 0.0),
+			DeadZoneKind::Radial => {
+				let scale = if distance > self.outer { 1.0 / distance } else { 1.0 };
+				(x * scale, y * scale)
+			},
+			DeadZoneKind::ScaledRadial => {
+				let scale = self.scale(distance) / distance;
+				(x * scale, y * scale)
+			}
+		}
+	}
+}
+
+/// The axes a dead zone or curve applies to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Axes {
+	Single(Axis),
+	Pair(Axis, Axis)
+}
+
+impl Axes {
+	pub fn contains(self, axis: Axis) -> bool {
+		match self {
+			Axes::Single(a) => a == axis,
+			Axes::Pair(x, y) => x == axis || y == axis
+		}
+	}
+	pub fn overlaps(self, other: Axes) -> bool {
+		match other {
+			Axes::Single(a) => self.contains(a),
+			Axes::Pair(x, y) => self.contains(x) || self.contains(y)
+		}
+	}
+}
+
+/// Dead zones for the axes of every joystick, which can be applied to events
+/// and to a `StatefulJoystick`.
+///
+/// Dead zones set for a specific joystick take priority over those set for
+/// every joystick.
+///
+/// ``` rust
+/// use reminisce::Event;
+/// use reminisce::deadzone::{DeadZone, DeadZones};
+///
+/// let mut zones = DeadZones::new();
+/// zones.set_pair(None, 0, 1, DeadZone::radial(0.2));
+/// // Jitter at rest is swallowed
+/// assert_eq!(zones.process(Event::AxisMoved(0, 0, 0.05)), vec![]);
+/// assert_eq!(zones.process(Event::AxisMoved(0, 0, 0.5)), vec![Event::AxisMoved(0, 0, 0.5)]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DeadZones {
+	zones: Vec<(Option<JoystickIndex>, Axes, DeadZone)>,
+	/// The last raw value of each axis
+	raw: HashMap<(JoystickIndex, Axis), f32>,
+	/// The last value of each axis that was emitted
+	emitted: HashMap<(JoystickIndex, Axis), f32>
+}
+
+impl DeadZones {
+	/// Create a new set of dead zones, which leaves every axis untouched
+	pub fn new() -> DeadZones {
+		DeadZones {
+			zones: Vec::new(),
+			raw: HashMap::new(),
+			emitted: HashMap::new()
+		}
+	}
+	/// Use the dead zone given for a single axis of the joystick given, or of
+	/// every joystick if none is given
+	pub fn set_axis(&mut self, joystick: Option<JoystickIndex>, axis: Axis, zone: DeadZone) {
+		self.set(joystick, Axes::Single(axis), zone);
+	}
+	/// Use the dead zone given for a stick made of the `x` and `y` axes of the
+	/// joystick given, or of every joystick if none is given
+	pub fn set_pair(&mut self, joystick: Option<JoystickIndex>, x: Axis, y: Axis, zone: DeadZone) {
+		self.set(joystick, Axes::Pair(x, y), zone);
+	}
+	fn set(&mut self, joystick: Option<JoystickIndex>, axes: Axes, zone: DeadZone) {
+		// Replace any dead zone that covered the same axes
+		self.zones.retain(|&(j, a, _)| j != joystick || !a.overlaps(axes));
+		self.zones.push((joystick, axes, zone));
+	}
+	/// Get the dead zone for an axis of a joystick and the axes it covers
+	fn find(&self, joystick: JoystickIndex, axis: Axis) -> Option<(Axes, DeadZone)> {
+		let specific = self.zones.iter().find(|&&(j, a, _)| j == Some(joystick) && a.contains(axis));
+		let general = self.zones.iter().find(|&&(j, a, _)| j.is_none() && a.contains(axis));
+		specific.or(general).map(|&(_, axes, zone)| (axes, zone))
+	}
+	/// Apply the dead zone for an axis of a joystick to its value, looking up
+	/// the value of the other axis of a stick with `axis_value`
+	pub fn apply<F>(&self, joystick: JoystickIndex, axis: Axis, value: f32, axis_value: F) -> f32
+		where F: Fn(Axis) -> f32 {
+		match self.find(joystick, axis) {
+			None => value,
+			Some((Axes::Single(_), zone)) => zone.apply_axis(value),
+			Some((Axes::Pair(x, y), zone)) if x == axis => zone.apply(value, axis_value(y)).0,
+			Some((Axes::Pair(x, _), zone)) => zone.apply(axis_value(x), value).1
+		}
+	}
+	/// Apply the dead zones to an event, returning the events to emit
+	/// instead.
+	///
+	/// Moving one axis of a stick can change the value of the other, so this
+	/// can return an event for each axis. Axes that haven't changed from the
+	/// last value emitted are left out.
+	pub fn process(&mut self, event: Event) -> Vec<Event> {
+		let (index, axis, value) = match event {
+			Event::AxisMoved(index, axis, value) => (index, axis, value),
+			Event::Disconnected(index) => {
+				self.raw.retain(|&(i, _), _| i != index);
+				self.emitted.retain(|&(i, _), _| i != index);
+				return vec![event]
+			},
+			_ => return vec![event]
+		};
+		self.raw.insert((index, axis), value);
+		let axes = match self.find(index, axis) {
+			Some((Axes::Pair(x, y), _)) => vec![x, y],
+			_ => vec![axis]
+		};
+		let mut events = Vec::with_capacity(axes.len());
+		for a in axes {
+			let raw = match self.raw.get(&(index, a)) {
+				Some(&raw) => raw,
+				None => continue
+			};
+			let processed = {
+				let raw_values = &self.raw;
+				self.apply(index, a, raw, |other| raw_values.get(&(index, other)).cloned().unwrap_or(0.0))
+			};
+			// Axes start at rest, so only changes from 0 need emitting
+			if self.emitted.insert((index, a), processed).unwrap_or(0.0) != processed {
+				events.push(Event::AxisMoved(index, a, processed));
+			}
+		}
+		events
+	}
+}
@@ -0,0 +1,267 @@
+//! The plumbing shared by the Linux backends: finding device nodes in a
+//! directory, following it with inotify and sleeping on them with epoll.
+use libc::{c_int, c_void, open, poll, pollfd, read, EAGAIN, ENODEV, O_NONBLOCK, O_RDONLY, POLLHUP, POLLIN};
+use inotify::INotify;
+use inotify::ffi;
+use glob::glob;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::io::{Error, ErrorKind};
+use std::mem::{self, ManuallyDrop};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::{Duration, Instant};
+use epoll::Epoll;
+use {BackendError, Event, Joystick, TimedEvent, Timestamp};
+
+/// A joystick read from a device node like `/dev/input/js0`
+pub trait Device: Joystick {
+	/// The settings every joystick is opened with
+	type Options: Copy + Default;
+	/// Get the start of the names of the device nodes, before the index
+	fn prefix() -> &'static str;
+	/// Open the device node at `path` as the joystick with the index given
+	fn open_with(path: &Path, index: u8, options: Self::Options) -> Result<Self, Error>;
+	/// Get the file descriptor that becomes readable when there is input
+	fn fd(&self) -> c_int;
+	/// Read the next event from the device, if there is one
+	fn poll(&mut self) -> Result<Option<TimedEvent>, BackendError>;
+	/// Get the current time on the clock the device stamps events with
+	fn now() -> Timestamp;
+}
+
+/// The joysticks whose device nodes are in a directory, opened and closed as
+/// the nodes are created and deleted
+pub struct Devices<J> where J: Device {
+	root: PathBuf,
+	joysticks: Vec<J>,
+	pending: VecDeque<TimedEvent>,
+	initial_events: bool,
+	options: J::Options,
+	inotify: ManuallyDrop<INotify>,
+	epoll: Epoll
+}
+
+impl<J> Devices<J> where J: Device {
+	/// Open every device node in `root` and start watching it for more
+	pub fn open<P>(root: P) -> Result<Devices<J>, BackendError> where P: AsRef<Path> {
+		let root = root.as_ref().to_owned();
+		let mut joysticks = Vec::with_capacity(4);
+		let pattern = root.join(format!("{}*", J::prefix()));
+		let entries = try!(glob(&pattern.to_string_lossy())
+			.map_err(|e| Error::new(ErrorKind::InvalidInput, e.msg)));
+		for entry in entries {
+			if let Ok(path) = entry {
+				let index = path.file_name().and_then(|name| name.to_str()).and_then(device_index::<J>);
+				if let Some(index) = index {
+					if let Ok(js) = J::open_with(&path, index, J::Options::default()) {
+						joysticks.push(js)
+					}
+				}
+			}
+		}
+		let time = J::now();
+		let pending = joysticks.iter().map(|js| TimedEvent { event: Event::Connected(js.index()), time: time }).collect();
+		let inotify = try!(INotify::init());
+		let devices = Devices {
+			root: root,
+			joysticks: joysticks,
+			pending: pending,
+			initial_events: false,
+			options: J::Options::default(),
+			inotify: ManuallyDrop::new(inotify),
+			epoll: try!(Epoll::new())
+		};
+		try!(devices.inotify.add_watch(&devices.root, ffi::IN_CREATE | ffi::IN_DELETE));
+		try!(devices.epoll.add(devices.inotify.fd));
+		for js in &devices.joysticks {
+			let _ = devices.epoll.add(js.fd());
+		}
+		Ok(devices)
+	}
+	/// Set whether each `Event::Connected` is followed by the events that
+	/// would bring the joystick from rest into its initial state
+	pub fn set_initial_events(&mut self, enabled: bool) {
+		self.initial_events = enabled;
+	}
+	/// Set the options joysticks are opened with from now on
+	pub fn set_options(&mut self, options: J::Options) {
+		self.options = options;
+	}
+	pub fn joysticks(&self) -> &[J] {
+		&self.joysticks
+	}
+	pub fn joysticks_mut(&mut self) -> &mut [J] {
+		&mut self.joysticks
+	}
+	/// Read every event that is ready, stopping at the first error
+	pub fn drain(&mut self) -> Result<Vec<TimedEvent>, BackendError> {
+		let mut events = Vec::new();
+		loop {
+			match self.try_poll_timed() {
+				Ok(Some(event)) => events.push(event),
+				Ok(None) => return Ok(events),
+				// Hand over what was read rather than lose it
+				Err(_) if !events.is_empty() => return Ok(events),
+				Err(error) => return Err(error)
+			}
+		}
+	}
+	/// Open and close joysticks as their devices are created and deleted
+	fn hotplug(&mut self) -> Result<(), BackendError> {
+		for e in try!(self.inotify.available_events()) {
+			let index = match device_index::<J>(&e.name) {
+				Some(index) => index,
+				None => continue
+			};
+			if e.is_create() {
+				if let Ok(joystick) = J::open_with(&self.root.join(&e.name), index, self.options) {
+					let _ = self.epoll.add(joystick.fd());
+					self.joysticks.push(joystick);
+					self.pending.push_back(TimedEvent { event: Event::Connected(index), time: J::now() });
+				}
+			} else if e.is_delete() {
+				if let Some(arr_index) = self.joysticks.iter().position(|js| js.index() == index) {
+					self.epoll.remove(self.joysticks[arr_index].fd());
+					self.joysticks.remove(arr_index);
+					self.pending.push_back(TimedEvent { event: Event::Disconnected(index), time: J::now() });
+				}
+			}
+		}
+		Ok(())
+	}
+	/// Stop tracking the joystick at the position given
+	fn remove(&mut self, arr_index: usize) {
+		self.epoll.remove(self.joysticks[arr_index].fd());
+		self.joysticks.remove(arr_index);
+	}
+	pub fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		try!(self.hotplug());
+		if let Some(event) = self.pending.pop_front() {
+			if let Event::Connected(index) = event.event {
+				if self.initial_events {
+					let state = self.joysticks.iter().find(|js| js.index() == index).and_then(|js| js.initial_state());
+					if let Some(state) = state {
+						for initial in state.events(index).into_iter().rev() {
+							self.pending.push_front(TimedEvent { event: initial, time: event.time });
+						}
+					}
+				}
+			}
+			return Ok(Some(event))
+		}
+		for arr_index in 0..self.joysticks.len() {
+			match self.joysticks[arr_index].poll() {
+				Ok(None) => (),
+				Ok(Some(event)) => {
+					if let Event::Disconnected(_) = event.event {
+						self.remove(arr_index);
+					}
+					return Ok(Some(event))
+				},
+				Err(BackendError::Io(error)) => {
+					// The device can't be read from any more, so treat it as unplugged
+					let index = self.joysticks[arr_index].index();
+					self.remove(arr_index);
+					self.pending.push_back(TimedEvent { event: Event::Disconnected(index), time: J::now() });
+					return Err(BackendError::Io(error))
+				},
+				Err(error) => return Err(error)
+			}
+		}
+		Ok(None)
+	}
+	/// Sleep on the joysticks and the directory until an event arrives or the
+	/// timeout elapses
+	pub fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		loop {
+			if let Some(event) = try!(self.try_poll_timed()) {
+				return Ok(Some(event))
+			}
+			let left = match deadline {
+				Some(deadline) => {
+					let now = Instant::now();
+					if now >= deadline {
+						return Ok(None)
+					}
+					Some(deadline - now)
+				},
+				None => None
+			};
+			try!(self.epoll.wait(left));
+		}
+	}
+}
+
+impl<J> AsRawFd for Devices<J> where J: Device {
+	fn as_raw_fd(&self) -> RawFd {
+		self.epoll.as_raw_fd()
+	}
+}
+
+impl<J> Drop for Devices<J> where J: Device {
+	fn drop(&mut self) {
+		let inotify = unsafe { ptr::read(&*self.inotify) };
+		let _ = inotify.close();
+	}
+}
+
+/// Parse the index out of a device node's name, like the 3 of `event3`
+fn device_index<J>(name: &str) -> Option<u8> where J: Device {
+	if name.starts_with(J::prefix()) {
+		name[J::prefix().len()..].parse().ok()
+	} else {
+		None
+	}
+}
+
+/// Open the device node at `path` for reading without blocking
+pub fn open_device(path: &Path) -> Result<c_int, Error> {
+	let path = try!(CString::new(path.as_os_str().as_bytes())
+		.map_err(|_| Error::new(ErrorKind::InvalidInput, "the path contains a nul byte")));
+	let fd = unsafe { open(path.as_ptr(), O_RDONLY | O_NONBLOCK) };
+	if fd == -1 {
+		Err(Error::last_os_error())
+	} else {
+		Ok(fd)
+	}
+}
+
+/// What reading a record from a device gave
+pub enum Record<T> {
+	Read(T),
+	/// Nothing is ready yet
+	Empty,
+	/// The device is gone, or the writer of a FIFO has closed it
+	Closed
+}
+
+/// Read a record the size of `T`, which must be plain data, from `fd`
+pub fn read_record<T>(fd: c_int) -> Result<Record<T>, Error> {
+	let mut record: T = unsafe { mem::zeroed() };
+	let size = unsafe { read(fd, &mut record as *mut T as *mut c_void, mem::size_of::<T>()) };
+	if size == -1 {
+		let error = Error::last_os_error();
+		return match error.raw_os_error() {
+			Some(EAGAIN) => Ok(Record::Empty),
+			Some(ENODEV) => Ok(Record::Closed),
+			_ => Err(error)
+		}
+	}
+	if size == 0 {
+		// Regular files are at their end until more is written, but a FIFO
+		// whose writer has gone would stay readable forever
+		let mut fds = pollfd { fd: fd, events: POLLIN, revents: 0 };
+		if unsafe { poll(&mut fds, 1, 0) } == 1 && fds.revents & POLLHUP != 0 {
+			return Ok(Record::Closed)
+		}
+	}
+	if size as usize != mem::size_of::<T>() {
+		Ok(Record::Empty)
+	} else {
+		Ok(Record::Read(record))
+	}
+}
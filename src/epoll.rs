@@ -0,0 +1,85 @@
+use libc::{c_int, epoll_create1, epoll_ctl, epoll_event, epoll_wait, EPOLLIN, EPOLL_CLOEXEC,
+	EPOLL_CTL_ADD, EPOLL_CTL_DEL, EINTR, close};
+use std::io::Error;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+use std::time::Duration;
+
+/// A set of file descriptors that can be slept on until any of them become
+/// readable
+pub struct Epoll {
+	fd: c_int
+}
+
+impl Epoll {
+	pub fn new() -> Result<Epoll, Error> {
+		let fd = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+		if fd == -1 {
+			Err(Error::last_os_error())
+		} else {
+			Ok(Epoll { fd: fd })
+		}
+	}
+	/// Start watching `fd` for input
+	///
+	/// This fails for regular files, which are always readable.
+	pub fn add(&self, fd: c_int) -> Result<(), Error> {
+		let mut event = epoll_event {
+			events: EPOLLIN as u32,
+			u64: fd as u64
+		};
+		if unsafe { epoll_ctl(self.fd, EPOLL_CTL_ADD, fd, &mut event) } == -1 {
+			Err(Error::last_os_error())
+		} else {
+			Ok(())
+		}
+	}
+	/// Stop watching `fd`
+	pub fn remove(&self, fd: c_int) {
+		unsafe {
+			epoll_ctl(self.fd, EPOLL_CTL_DEL, fd, ptr::null_mut());
+		}
+	}
+	/// Sleep until any of the file descriptors are readable or the timeout
+	/// given has elapsed, returning true if any are readable
+	///
+	/// If no timeout is given this can sleep forever.
+	pub fn wait(&self, timeout: Option<Duration>) -> Result<bool, Error> {
+		let timeout = match timeout {
+			// Round up so this never wakes before the timeout
+			Some(timeout) => {
+				let millis = timeout.as_secs() * 1000 + (timeout.subsec_nanos() as u64 + 999_999) / 1_000_000;
+				if millis > c_int::max_value() as u64 { c_int::max_value() } else { millis as c_int }
+			},
+			None => -1
+		};
+		let mut event: epoll_event = unsafe { mem::zeroed() };
+		match unsafe { epoll_wait(self.fd, &mut event, 1, timeout) } {
+			-1 => {
+				let error = Error::last_os_error();
+				if error.raw_os_error() == Some(EINTR) {
+					Ok(false)
+				} else {
+					Err(error)
+				}
+			},
+			0 => Ok(false),
+			_ => Ok(true)
+		}
+	}
+}
+
+impl AsRawFd for Epoll {
+	fn as_raw_fd(&self) -> RawFd {
+		self.fd
+	}
+}
+
+impl Drop for Epoll {
+	fn drop(&mut self) {
+		unsafe {
+			close(self.fd);
+		}
+	}
+}
@@ -0,0 +1,525 @@
+use libc::{c_char, c_int, c_ulong, c_void, timespec, timeval, clock_gettime, CLOCK_MONOTONIC, ioctl};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::time::Duration;
+use devices::{open_device, read_record, Device, Devices, Record};
+use {AxisInfo, Backend, BackendError, ButtonInfo, Capabilities, DeviceId, Event, HatPos, JoystickState, Poller,
+	TimedEvent, Timestamp};
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const EV_FF: u16 = 0x15;
+const EV_MAX: u16 = 0x1f;
+const KEY_MAX: u16 = 0x2ff;
+const ABS_MAX: u16 = 0x3f;
+const BTN_MISC: u16 = 0x100;
+const BTN_JOYSTICK: u16 = 0x120;
+const BTN_DIGI: u16 = 0x140;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT3Y: u16 = 0x17;
+const SYN_REPORT: u16 = 0x00;
+const SYN_DROPPED: u16 = 0x03;
+const ENOTTY: i32 = 25;
+const EVIOCGNAME_LEN: usize = 64;
+const EVIOCSCLOCKID: c_ulong = 1074021792;
+
+/// Build a read ioctl request number for the evdev interface
+fn eviocr(nr: u16, size: usize) -> c_ulong {
+	(2 << 30) | ((size as c_ulong) << 16) | ((b'E' as c_ulong) << 8) | nr as c_ulong
+}
+
+fn eviocgbit(ev: u16, len: usize) -> c_ulong {
+	eviocr(0x20 + ev, len)
+}
+
+fn eviocgkey(len: usize) -> c_ulong {
+	eviocr(0x18, len)
+}
+
+fn eviocgabs(abs: u16) -> c_ulong {
+	eviocr(0x40 + abs, mem::size_of::<AbsInfo>())
+}
+
+fn test_bit(bits: &[u8], bit: u16) -> bool {
+	bits[bit as usize / 8] & (1 << (bit % 8)) != 0
+}
+
+extern {
+	fn close(fd: c_int) -> c_int;
+}
+
+/// A backend that reads joysticks and gamepads through the evdev interface
+/// at `/dev/input/event*`.
+///
+/// Unlike the legacy joystick interface this reports hats, the real ranges of
+/// axes and the kernel codes of every control.
+pub struct Native {
+	devices: Devices<NativeJoystick>
+}
+impl Native {
+	/// Create a new backend that scans `root` instead of `/dev/input` for
+	/// `event*` devices.
+	///
+	/// Devices that can't report their capabilities, such as plain files or
+	/// pipes carrying `input_event` records, are assumed to have every
+	/// standard joystick button and every absolute axis, so this can be
+	/// pointed at a directory of fixtures.
+	///
+	/// ``` rust
+	/// use reminisce::{Backend, Event};
+	/// use reminisce::evdev::Native;
+	/// use std::fs::{self, File};
+	/// use std::io::Write;
+	/// use std::mem;
+	///
+	/// let root = std::env::temp_dir().join("reminisce-evdev-with-root");
+	/// fs::create_dir_all(&root).unwrap();
+	/// // A zeroed timestamp, then EV_KEY, BTN_TRIGGER and a value of 1
+	/// let mut record = vec![0u8; 2 * mem::size_of::<usize>()];
+	/// record.extend(&1u16.to_ne_bytes());
+	/// record.extend(&0x120u16.to_ne_bytes());
+	/// record.extend(&1i32.to_ne_bytes());
+	/// File::create(root.join("event0")).unwrap().write_all(&record).unwrap();
+	///
+	/// let mut backend = Native::with_root(&root).unwrap();
+	/// assert_eq!(backend.poll(), Some(Event::Connected(0)));
+	/// assert_eq!(backend.poll(), Some(Event::ButtonPressed(0, 0)));
+	/// assert_eq!(backend.poll(), None);
+	/// # drop(backend);
+	/// # fs::remove_dir_all(&root).unwrap();
+	/// ```
+	pub fn with_root<P>(root: P) -> Result<Native, BackendError> where P: AsRef<Path> {
+		Devices::open(root).map(|devices| Native { devices: devices })
+	}
+	/// Set whether each `Event::Connected` is followed by the events that
+	/// would bring the joystick from rest into its initial state.
+	///
+	/// This is off by default.
+	pub fn set_initial_events(&mut self, enabled: bool) {
+		self.devices.set_initial_events(enabled);
+	}
+	/// Read every event that is ready, for use with `as_raw_fd`.
+	///
+	/// This stops at the first error, so call it until it returns no events.
+	pub fn drain(&mut self) -> Result<Vec<TimedEvent>, BackendError> {
+		self.devices.drain()
+	}
+}
+/// This is readable whenever `drain` has something to return.
+impl AsRawFd for Native {
+	fn as_raw_fd(&self) -> RawFd {
+		self.devices.as_raw_fd()
+	}
+}
+impl Backend for Native {
+	type Joystick = NativeJoystick;
+	fn try_new() -> Result<Native, BackendError> {
+		Native::with_root("/dev/input")
+	}
+	fn joysticks(&self) -> &[NativeJoystick] {
+		self.devices.joysticks()
+	}
+	fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		self.devices.try_poll_timed()
+	}
+	/// This sleeps until a joystick or the device directory changes
+	fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+		self.devices.try_wait_timed(timeout)
+	}
+}
+
+impl<'a> IntoIterator for &'a mut Native {
+	type Item = Event;
+	type IntoIter = Poller<'a, Native>;
+	fn into_iter(self) -> Poller<'a, Native> {
+		self.iter()
+	}
+}
+
+/// Get the current time on the monotonic clock the joysticks are set to use
+fn now() -> Timestamp {
+	let mut time: timespec = unsafe { mem::zeroed() };
+	unsafe {
+		clock_gettime(CLOCK_MONOTONIC, &mut time);
+	}
+	(time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000) as Timestamp
+}
+
+/// An absolute axis and the range it reports values in
+struct AbsAxis {
+	code: u16,
+	min: i32,
+	max: i32
+}
+
+impl AbsAxis {
+	/// Scale a value from this axis into the range `-1` to `1`
+	fn normalize(&self, value: i32) -> f32 {
+		if self.max <= self.min {
+			return 0.0
+		}
+		let value = value as f32 - self.min as f32;
+		(value * 2.0 / (self.max as f32 - self.min as f32) - 1.0).max(-1.0).min(1.0)
+	}
+}
+
+/// A hat synthesized from a pair of `ABS_HAT*` axes
+struct HatAxes {
+	number: u16,
+	x: i32,
+	y: i32
+}
+
+impl HatAxes {
+	fn pos(&self) -> HatPos {
+		HatPos::from_xy(self.x.signum() as i8, self.y.signum() as i8)
+	}
+}
+
+/// Represents a system joystick accessed through evdev
+pub struct NativeJoystick {
+	index: u8,
+	fd: c_int,
+	name: String,
+	/// The key codes of the buttons, in button order
+	buttons: Vec<u16>,
+	/// The absolute axes that aren't part of a hat, in axis order
+	axes: Vec<AbsAxis>,
+	hats: Vec<HatAxes>,
+	force_feedback: bool,
+	device_id: Option<DeviceId>,
+	/// The state of the joystick when it was opened
+	initial: JoystickState,
+	/// The state of the joystick as reported by the events so far
+	state: JoystickState,
+	/// Whether the kernel dropped events, so the rest of the report must be
+	/// skipped and the state read again
+	dropped: bool,
+	/// The events that bring the state back in line after a drop
+	resynced: VecDeque<TimedEvent>
+}
+
+impl NativeJoystick {
+	/// Open the event device at `path` as the joystick with the index given.
+	///
+	/// This fails if the device doesn't have any joystick or gamepad buttons.
+	pub fn open_at<P>(path: P, index: u8) -> Result<NativeJoystick, Error> where P: AsRef<Path> {
+		let path = path.as_ref();
+		let fd = try!(open_device(path));
+		let mut joystick = NativeJoystick {
+			index: index,
+			fd: fd,
+			name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+			buttons: Vec::new(),
+			axes: Vec::new(),
+			hats: Vec::new(),
+			force_feedback: false,
+			device_id: None,
+			initial: JoystickState::new(0, 0, 0),
+			state: JoystickState::new(0, 0, 0),
+			dropped: false,
+			resynced: VecDeque::new()
+		};
+		let mut keys = [0u8; KEY_MAX as usize / 8 + 1];
+		let mut held = [0u8; KEY_MAX as usize / 8 + 1];
+		let mut abs = [0u8; ABS_MAX as usize / 8 + 1];
+		if unsafe { ioctl(fd, eviocgbit(EV_KEY, keys.len()), keys.as_mut_ptr() as *mut c_void) } == -1 {
+			let error = Error::last_os_error();
+			if error.raw_os_error() != Some(ENOTTY) {
+				return Err(error)
+			}
+			// Not an input device, so assume the standard joystick buttons
+			// and every axis
+			for code in BTN_JOYSTICK..BTN_DIGI {
+				keys[code as usize / 8] |= 1 << (code % 8);
+			}
+			abs = [0xff; ABS_MAX as usize / 8 + 1];
+		} else {
+			if !(BTN_JOYSTICK..BTN_DIGI).any(|code| test_bit(&keys, code)) {
+				return Err(Error::new(ErrorKind::InvalidInput, "not a joystick"))
+			}
+			let mut types = [0u8; EV_MAX as usize / 8 + 1];
+			unsafe {
+				ioctl(fd, eviocgbit(0, types.len()), types.as_mut_ptr() as *mut c_void);
+				// Stamp events with the same clock as `now` rather than the wall clock
+				let mut clock = CLOCK_MONOTONIC;
+				ioctl(fd, EVIOCSCLOCKID, &mut clock as *mut c_int as *mut c_void);
+				ioctl(fd, eviocgbit(EV_ABS, abs.len()), abs.as_mut_ptr() as *mut c_void);
+				ioctl(fd, eviocgkey(held.len()), held.as_mut_ptr() as *mut c_void);
+				let mut name = [0u8; EVIOCGNAME_LEN];
+				if ioctl(fd, eviocr(0x06, EVIOCGNAME_LEN), name.as_mut_ptr() as *mut c_void) > 0 {
+					let name = CStr::from_ptr(name.as_ptr() as *const c_char);
+					joystick.name = name.to_string_lossy().into_owned();
+				}
+			}
+			joystick.force_feedback = test_bit(&types, EV_FF);
+			let mut id = [0u16; 4];
+			if unsafe { ioctl(fd, eviocr(0x02, mem::size_of_val(&id)), id.as_mut_ptr() as *mut c_void) } != -1 {
+				joystick.device_id = Some(DeviceId::new(id[0], id[1], id[2], id[3], &joystick.name));
+			}
+		}
+		// Buttons are ordered with the joystick and gamepad buttons first, like SDL
+		joystick.buttons = (BTN_JOYSTICK..KEY_MAX + 1).chain(BTN_MISC..BTN_JOYSTICK)
+			.filter(|&code| test_bit(&keys, code))
+			.take(255)
+			.collect();
+		let mut values = Vec::new();
+		for code in 0..ABS_MAX + 1 {
+			if !test_bit(&abs, code) {
+				continue
+			}
+			if code >= ABS_HAT0X && code <= ABS_HAT3Y {
+				let number = (code - ABS_HAT0X) / 2;
+				let value = abs_info(fd, code).map(|info| info.value).unwrap_or(0);
+				let hat = match joystick.hats.iter().position(|hat| hat.number == number) {
+					Some(hat) => hat,
+					None => {
+						joystick.hats.push(HatAxes { number: number, x: 0, y: 0 });
+						joystick.hats.len() - 1
+					}
+				};
+				if (code - ABS_HAT0X) % 2 == 0 {
+					joystick.hats[hat].x = value;
+				} else {
+					joystick.hats[hat].y = value;
+				}
+			} else {
+				let axis = match abs_info(fd, code) {
+					Some(info) => {
+						values.push(info.value);
+						AbsAxis { code: code, min: info.minimum, max: info.maximum }
+					},
+					None => {
+						values.push(0);
+						AbsAxis { code: code, min: -32767, max: 32767 }
+					}
+				};
+				joystick.axes.push(axis);
+			}
+		}
+		joystick.initial = JoystickState {
+			buttons: joystick.buttons.iter().map(|&code| test_bit(&held, code)).collect(),
+			axes: joystick.axes.iter().zip(values).map(|(axis, value)| axis.normalize(value)).collect(),
+			hats: joystick.hats.iter().map(|hat| hat.pos()).collect(),
+			time: now()
+		};
+		joystick.state = joystick.initial.clone();
+		Ok(joystick)
+	}
+	/// Read the state of every control from the device, updating the hats
+	fn read_state(&mut self) -> Option<JoystickState> {
+		let mut held = [0u8; KEY_MAX as usize / 8 + 1];
+		if unsafe { ioctl(self.fd, eviocgkey(held.len()), held.as_mut_ptr() as *mut c_void) } == -1 {
+			return None
+		}
+		let fd = self.fd;
+		for hat in &mut self.hats {
+			if let Some(info) = abs_info(fd, ABS_HAT0X + hat.number * 2) {
+				hat.x = info.value;
+			}
+			if let Some(info) = abs_info(fd, ABS_HAT0X + hat.number * 2 + 1) {
+				hat.y = info.value;
+			}
+		}
+		let state = &self.state;
+		Some(JoystickState {
+			buttons: self.buttons.iter().map(|&code| test_bit(&held, code)).collect(),
+			axes: self.axes.iter().enumerate().map(|(index, axis)| match abs_info(fd, axis.code) {
+				Some(info) => axis.normalize(info.value),
+				None => state.axes.get(index).cloned().unwrap_or(0.0)
+			}).collect(),
+			hats: self.hats.iter().map(|hat| hat.pos()).collect(),
+			time: now()
+		})
+	}
+	/// Convert a raw input event into an `Event`, updating the hats as needed
+	fn translate(&mut self, event: &InputEvent) -> Option<Event> {
+		match event._type {
+			EV_KEY => {
+				let button = match self.buttons.iter().position(|&code| code == event.code) {
+					Some(button) => button as u8,
+					None => return None
+				};
+				match event.value {
+					0 => Some(Event::ButtonReleased(self.index, button)),
+					1 => Some(Event::ButtonPressed(self.index, button)),
+					_ => None
+				}
+			},
+			EV_ABS if event.code >= ABS_HAT0X && event.code <= ABS_HAT3Y => {
+				let number = (event.code - ABS_HAT0X) / 2;
+				let x_moved = (event.code - ABS_HAT0X) % 2 == 0;
+				self.hats.iter_mut().position(|hat| hat.number == number).map(|index| {
+					let hat = &mut self.hats[index];
+					if x_moved {
+						hat.x = event.value;
+					} else {
+						hat.y = event.value;
+					}
+					Event::HatMoved(self.index, index as u8, hat.pos())
+				})
+			},
+			EV_ABS => {
+				self.axes.iter().position(|axis| axis.code == event.code).map(|index|
+					Event::AxisMoved(self.index, index as u8, self.axes[index].normalize(event.value))
+				)
+			},
+			_ => None
+		}
+	}
+}
+
+impl Device for NativeJoystick {
+	type Options = ();
+	fn prefix() -> &'static str {
+		"event"
+	}
+	fn open_with(path: &Path, index: u8, _: ()) -> Result<NativeJoystick, Error> {
+		NativeJoystick::open_at(path, index)
+	}
+	fn fd(&self) -> c_int {
+		self.fd
+	}
+	fn poll(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		if let Some(event) = self.resynced.pop_front() {
+			return Ok(Some(event))
+		}
+		loop {
+			let event: InputEvent = match read_record(self.fd) {
+				Ok(Record::Read(event)) => event,
+				Ok(Record::Empty) => return Ok(None),
+				Ok(Record::Closed) => return Ok(Some(TimedEvent {
+					event: Event::Disconnected(self.index),
+					time: now()
+				})),
+				Err(err) => return Err(BackendError::Io(err))
+			};
+			let millis = event.time.tv_sec as u64 * 1000 + event.time.tv_usec as u64 / 1000;
+			match (event._type, event.code) {
+				(EV_SYN, SYN_DROPPED) => self.dropped = true,
+				(EV_SYN, SYN_REPORT) if self.dropped => {
+					// The kernel's buffer overflowed, so catch up with the
+					// state of the device as it is now
+					self.dropped = false;
+					if let Some(state) = self.read_state() {
+						let index = self.index;
+						let events = self.state.diff(&state, index);
+						self.resynced.extend(events.into_iter().map(|event| TimedEvent { event: event, time: millis as Timestamp }));
+						self.state = state;
+					}
+					if let Some(event) = self.resynced.pop_front() {
+						return Ok(Some(event))
+					}
+				},
+				_ if self.dropped => (),
+				_ => if let Some(translated) = self.translate(&event) {
+					self.state.process(translated);
+					return Ok(Some(TimedEvent {
+						event: translated,
+						time: millis as Timestamp
+					}))
+				}
+			}
+		}
+	}
+	fn now() -> Timestamp {
+		now()
+	}
+}
+
+impl ::Joystick for NativeJoystick {
+	type OpenError = Error;
+	/// This tries to open the interface `/dev/input/event...` and will return
+	/// the OS-level error if it fails to open this or it isn't a joystick
+	fn open(index: u8) -> Result<NativeJoystick, Error> {
+		NativeJoystick::open_at(format!("/dev/input/event{}", index), index)
+	}
+	fn connected(&self) -> bool {
+		true
+	}
+	fn num_hats(&self) -> u8 {
+		self.hats.len() as u8
+	}
+	fn num_axes(&self) -> u8 {
+		self.axes.len() as u8
+	}
+	fn num_buttons(&self) -> u8 {
+		self.buttons.len() as u8
+	}
+	fn id(&self) -> Cow<str> {
+		Cow::Borrowed(&self.name)
+	}
+	fn index(&self) -> u8 {
+		self.index
+	}
+	fn device_id(&self) -> Option<DeviceId> {
+		self.device_id
+	}
+	/// This is not supported by evdev so None is returned every time
+	fn battery(&self) -> Option<f32> {
+		None
+	}
+	fn initial_state(&self) -> Option<JoystickState> {
+		Some(self.initial.clone())
+	}
+	fn capabilities(&self) -> Capabilities {
+		Capabilities {
+			axes: self.axes.iter().map(|axis| AxisInfo {
+				code: Some(axis.code),
+				min: axis.min,
+				max: axis.max
+			}).collect(),
+			buttons: self.buttons.iter().map(|&code| ButtonInfo { code: Some(code) }).collect(),
+			hats: self.hats.len() as u8,
+			force_feedback: self.force_feedback,
+			battery: false
+		}
+	}
+}
+
+impl Drop for NativeJoystick {
+	/// Close the joystick's file descriptor
+	fn drop(&mut self) {
+		unsafe {
+			close(self.fd);
+		}
+	}
+}
+
+/// Get the range and current value of an absolute axis
+fn abs_info(fd: c_int, code: u16) -> Option<AbsInfo> {
+	let mut info: AbsInfo = unsafe { mem::zeroed() };
+	if unsafe { ioctl(fd, eviocgabs(code), &mut info as *mut AbsInfo as *mut c_void) } == -1 {
+		None
+	} else {
+		Some(info)
+	}
+}
+
+#[repr(C)]
+struct AbsInfo {
+	value: i32,
+	minimum: i32,
+	maximum: i32,
+	fuzz: i32,
+	flat: i32,
+	resolution: i32
+}
+
+#[repr(C)]
+pub struct InputEvent {
+	/// timestamp
+	time: timeval,
+	/// event type
+	_type: u16,
+	/// key or axis code
+	code: u16,
+	/// value
+	value: i32
+}
@@ -0,0 +1,518 @@
+//! A standard gamepad layout on top of a `Backend`, so buttons and axes can be
+//! referred to by their place on the pad rather than by an index that
+//! differs between devices and backends.
+use std::collections::{HashMap, HashSet, VecDeque};
+use mapping::MappingDb;
+use {Axis, Backend, Button, Capabilities, Event, Hat, HatPos, Joystick, JoystickIndex};
+
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const BTN_NORTH: u16 = 0x133;
+const BTN_WEST: u16 = 0x134;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+const BTN_SELECT: u16 = 0x13a;
+const BTN_START: u16 = 0x13b;
+const BTN_MODE: u16 = 0x13c;
+const BTN_THUMBL: u16 = 0x13d;
+const BTN_THUMBR: u16 = 0x13e;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_DOWN: u16 = 0x221;
+const BTN_DPAD_LEFT: u16 = 0x222;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+/// A button on a standard gamepad, named by its position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+	/// The bottom face button, like A on an Xbox pad or Cross on a PlayStation pad
+	South,
+	/// The right face button, like B on an Xbox pad or Circle on a PlayStation pad
+	East,
+	/// The left face button, like X on an Xbox pad or Square on a PlayStation pad
+	West,
+	/// The top face button, like Y on an Xbox pad or Triangle on a PlayStation pad
+	North,
+	Back,
+	Guide,
+	Start,
+	/// Pressing in the left stick
+	LeftStick,
+	/// Pressing in the right stick
+	RightStick,
+	LeftShoulder,
+	RightShoulder,
+	DPadUp,
+	DPadDown,
+	DPadLeft,
+	DPadRight
+}
+
+/// An axis on a standard gamepad, named by its position.
+///
+/// Sticks range from `-1` to `1`, with negative values being left and up,
+/// and triggers range from `0` when released to `1` when fully pressed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+	LeftX,
+	LeftY,
+	RightX,
+	RightY,
+	LeftTrigger,
+	RightTrigger
+}
+
+impl GamepadAxis {
+	/// Check if this is a trigger, which only reports positive values
+	pub fn is_trigger(self) -> bool {
+		self == GamepadAxis::LeftTrigger || self == GamepadAxis::RightTrigger
+	}
+}
+
+/// An event emitted by a gamepad.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+	/// Fired when a gamepad is connected with its index.
+	Connected(JoystickIndex),
+	/// Fired when a gamepad is disconnected with its index.
+	Disconnected(JoystickIndex),
+	/// Fired when a button is pressed with the gamepad index and the button.
+	ButtonPressed(JoystickIndex, GamepadButton),
+	/// Fired when a button is released with the gamepad index and the button.
+	ButtonReleased(JoystickIndex, GamepadButton),
+	/// Fired when an axis is moved with the gamepad index, the axis and its
+	/// value.
+	AxisMoved(JoystickIndex, GamepadAxis, f32)
+}
+
+/// The part of an axis's range that is used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AxisRange {
+	/// The whole range from `-1` to `1`
+	Full,
+	/// Only the range from `0` to `1`
+	Positive,
+	/// Only the range from `0` to `-1`
+	Negative
+}
+
+/// A control on a joystick that can be bound to a gamepad control.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Source {
+	Button(Button),
+	/// An axis, the part of it that is used and whether it is inverted
+	Axis(Axis, AxisRange, bool),
+	/// A hat being held in a direction, including the diagonals next to it
+	Hat(Hat, HatPos)
+}
+
+/// A gamepad control that a joystick's control can be bound to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+	Button(GamepadButton),
+	/// An axis and the part of it that is driven
+	Axis(GamepadAxis, AxisRange)
+}
+
+/// A joystick control bound to a gamepad control.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Binding {
+	pub source: Source,
+	pub target: Target
+}
+
+/// How to translate the controls of a model of joystick into gamepad
+/// controls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mapping {
+	/// The name of the gamepad
+	pub name: String,
+	pub bindings: Vec<Binding>
+}
+
+impl Mapping {
+	/// Guess the mapping of a joystick from the kernel codes of its controls.
+	///
+	/// Returns none if the backend doesn't report any codes.
+	///
+	/// ``` rust
+	/// use reminisce::{ButtonInfo, Capabilities};
+	/// use reminisce::gamepad::{Binding, GamepadButton, Mapping, Source, Target};
+	///
+	/// let capabilities = Capabilities {
+	///     axes: Vec::new(),
+	///     // BTN_NORTH then BTN_SOUTH
+	///     buttons: vec![ButtonInfo { code: Some(0x133) }, ButtonInfo { code: Some(0x130) }],
+	///     hats: 0,
+	///     force_feedback: false,
+	///     battery: false
+	/// };
+	/// let mapping = Mapping::from_capabilities("Pad", &capabilities).unwrap();
+	/// assert_eq!(mapping.bindings[1], Binding {
+	///     source: Source::Button(1),
+	///     target: Target::Button(GamepadButton::South)
+	/// });
+	/// ```
+	pub fn from_capabilities(name: &str, capabilities: &Capabilities) -> Option<Mapping> {
+		let mut bindings = Vec::new();
+		let mut known = false;
+		for (button, info) in capabilities.buttons.iter().enumerate() {
+			let code = match info.code {
+				Some(code) => code,
+				None => continue
+			};
+			known = true;
+			let target = match code {
+				BTN_SOUTH => Target::Button(GamepadButton::South),
+				BTN_EAST => Target::Button(GamepadButton::East),
+				BTN_NORTH => Target::Button(GamepadButton::North),
+				BTN_WEST => Target::Button(GamepadButton::West),
+				BTN_TL => Target::Button(GamepadButton::LeftShoulder),
+				BTN_TR => Target::Button(GamepadButton::RightShoulder),
+				BTN_TL2 => Target::Axis(GamepadAxis::LeftTrigger, AxisRange::Full),
+				BTN_TR2 => Target::Axis(GamepadAxis::RightTrigger, AxisRange::Full),
+				BTN_SELECT => Target::Button(GamepadButton::Back),
+				BTN_START => Target::Button(GamepadButton::Start),
+				BTN_MODE => Target::Button(GamepadButton::Guide),
+				BTN_THUMBL => Target::Button(GamepadButton::LeftStick),
+				BTN_THUMBR => Target::Button(GamepadButton::RightStick),
+				BTN_DPAD_UP => Target::Button(GamepadButton::DPadUp),
+				BTN_DPAD_DOWN => Target::Button(GamepadButton::DPadDown),
+				BTN_DPAD_LEFT => Target::Button(GamepadButton::DPadLeft),
+				BTN_DPAD_RIGHT => Target::Button(GamepadButton::DPadRight),
+				_ => continue
+			};
+			bindings.push(Binding { source: Source::Button(button as Button), target: target });
+		}
+		for (axis, info) in capabilities.axes.iter().enumerate() {
+			let code = match info.code {
+				Some(code) => code,
+				None => continue
+			};
+			known = true;
+			let axis = axis as Axis;
+			let full = |target| Binding { source: Source::Axis(axis, AxisRange::Full, false), target: Target::Axis(target, AxisRange::Full) };
+			let half = |range, target| Binding { source: Source::Axis(axis, range, false), target: Target::Button(target) };
+			match code {
+				ABS_X => bindings.push(full(GamepadAxis::LeftX)),
+				ABS_Y => bindings.push(full(GamepadAxis::LeftY)),
+				ABS_Z => bindings.push(full(GamepadAxis::LeftTrigger)),
+				ABS_RX => bindings.push(full(GamepadAxis::RightX)),
+				ABS_RY => bindings.push(full(GamepadAxis::RightY)),
+				ABS_RZ => bindings.push(full(GamepadAxis::RightTrigger)),
+				// The joystick interface reports the hat as a pair of axes
+				ABS_HAT0X => {
+					bindings.push(half(AxisRange::Negative, GamepadButton::DPadLeft));
+					bindings.push(half(AxisRange::Positive, GamepadButton::DPadRight));
+				},
+				ABS_HAT0Y => {
+					bindings.push(half(AxisRange::Negative, GamepadButton::DPadUp));
+					bindings.push(half(AxisRange::Positive, GamepadButton::DPadDown));
+				},
+				_ => ()
+			}
+		}
+		if !known {
+			return None
+		}
+		if capabilities.hats > 0 {
+			for &(pos, target) in &[
+				(HatPos::Up, GamepadButton::DPadUp),
+				(HatPos::Down, GamepadButton::DPadDown),
+				(HatPos::Left, GamepadButton::DPadLeft),
+				(HatPos::Right, GamepadButton::DPadRight)
+			] {
+				bindings.push(Binding { source: Source::Hat(0, pos), target: Target::Button(target) });
+			}
+		}
+		Some(Mapping {
+			name: name.to_owned(),
+			bindings: bindings
+		})
+	}
+}
+
+/// The value of a source, either across its full range from `-1` to `1` or
+/// as a magnitude from `0` to `1` for half axes, buttons and hats
+#[derive(Copy, Clone)]
+enum Value {
+	Full(f32),
+	Half(f32)
+}
+
+impl Value {
+	/// Get the value as a magnitude from `0` to `1`
+	fn magnitude(self) -> f32 {
+		match self {
+			Value::Full(value) => (value + 1.0) / 2.0,
+			Value::Half(value) => value
+		}
+	}
+}
+
+/// A backend that reports joysticks as standard gamepads.
+///
+/// Joysticks are mapped when they connect, unless a mapping has been set for
+/// them, by looking up their GUID in the gamepad's `MappingDb` and otherwise
+/// by guessing from the kernel codes of their controls. Joysticks without a
+/// mapping don't emit any events other than connecting and disconnecting.
+///
+/// ``` rust
+/// use reminisce::{Backend, Native};
+/// use reminisce::gamepad::{Gamepad, GamepadButton, GamepadEvent};
+/// if let Ok(backend) = Native::try_new() {
+///     let mut gamepad = Gamepad::new(backend);
+///     for event in gamepad.iter().take(30) {
+///         if let GamepadEvent::ButtonPressed(_, GamepadButton::South) = event {
+///             println!("Jump!")
+///         }
+///     }
+/// }
+/// ```
+///
+/// Any backend works, including one that replays a script of events:
+///
+/// ``` rust
+/// use reminisce::{Event, HatPos};
+/// use reminisce::gamepad::*;
+/// # use reminisce::testing::Script;
+///
+/// let bind = |source, target| Binding { source: source, target: target };
+/// let mapping = Mapping {
+///     name: "Scripted pad".to_owned(),
+///     bindings: vec![
+///         bind(Source::Button(0), Target::Button(GamepadButton::South)),
+///         bind(Source::Axis(1, AxisRange::Full, true), Target::Axis(GamepadAxis::LeftY, AxisRange::Full)),
+///         bind(Source::Axis(2, AxisRange::Full, false), Target::Axis(GamepadAxis::LeftTrigger, AxisRange::Full)),
+///         bind(Source::Axis(3, AxisRange::Positive, false), Target::Button(GamepadButton::RightShoulder)),
+///         bind(Source::Hat(0, HatPos::Up), Target::Button(GamepadButton::DPadUp)),
+///         bind(Source::Hat(0, HatPos::Left), Target::Button(GamepadButton::DPadLeft))
+///     ]
+/// };
+/// let script = vec![
+///     Event::Connected(0),
+///     Event::ButtonPressed(0, 0),
+///     Event::AxisMoved(0, 1, 0.5),
+///     Event::AxisMoved(0, 2, 0.0),
+///     Event::AxisMoved(0, 3, 0.9),
+///     Event::AxisMoved(0, 3, 0.95),
+///     Event::AxisMoved(0, 3, -1.0),
+///     Event::HatMoved(0, 0, HatPos::UpLeft),
+///     Event::HatMoved(0, 0, HatPos::Left),
+///     Event::ButtonReleased(0, 0)
+/// ];
+/// # let mut backend = Script::new(Vec::new());
+/// # for event in script { backend.push(event, 0) }
+/// let mut gamepad = Gamepad::new(backend);
+/// gamepad.set_mapping(0, mapping);
+/// assert_eq!(gamepad.iter().collect::<Vec<_>>(), vec![
+///     GamepadEvent::Connected(0),
+///     GamepadEvent::ButtonPressed(0, GamepadButton::South),
+///     // Inverted
+///     GamepadEvent::AxisMoved(0, GamepadAxis::LeftY, -0.5),
+///     // Triggers go from 0 to 1
+///     GamepadEvent::AxisMoved(0, GamepadAxis::LeftTrigger, 0.5),
+///     // Pressed once, however far the axis goes past halfway
+///     GamepadEvent::ButtonPressed(0, GamepadButton::RightShoulder),
+///     GamepadEvent::ButtonReleased(0, GamepadButton::RightShoulder),
+///     // Diagonals press both directions
+///     GamepadEvent::ButtonPressed(0, GamepadButton::DPadUp),
+///     GamepadEvent::ButtonPressed(0, GamepadButton::DPadLeft),
+///     GamepadEvent::ButtonReleased(0, GamepadButton::DPadUp),
+///     GamepadEvent::ButtonReleased(0, GamepadButton::South)
+/// ]);
+/// ```
+pub struct Gamepad<B> where B: Backend {
+	backend: B,
+	db: MappingDb,
+	/// The mappings set with `set_mapping`, which outlive the joysticks
+	mappings: HashMap<JoystickIndex, Mapping>,
+	/// The mappings looked up or guessed for the joysticks that are connected
+	found: HashMap<JoystickIndex, Mapping>,
+	/// The gamepad buttons that are held down
+	pressed: HashSet<(JoystickIndex, GamepadButton)>,
+	/// The last position of each hat
+	hats: HashMap<(JoystickIndex, Hat), HatPos>,
+	pending: VecDeque<GamepadEvent>
+}
+
+impl<B> Gamepad<B> where B: Backend {
+	/// Create a new gamepad layer over the backend given
+	pub fn new(backend: B) -> Gamepad<B> {
+		Gamepad::with_db(backend, MappingDb::new())
+	}
+	/// Create a new gamepad layer over the backend given, mapping joysticks
+	/// with the mappings in `db`
+	pub fn with_db(backend: B, db: MappingDb) -> Gamepad<B> {
+		Gamepad {
+			backend: backend,
+			db: db,
+			mappings: HashMap::new(),
+			found: HashMap::new(),
+			pressed: HashSet::new(),
+			hats: HashMap::new(),
+			pending: VecDeque::new()
+		}
+	}
+	/// Get a reference to the backend
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+	/// Get a mutable reference to the backend
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+	/// Stop translating events and return the backend
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+	/// Get a reference to the mappings that joysticks are looked up in
+	pub fn db(&self) -> &MappingDb {
+		&self.db
+	}
+	/// Get a mutable reference to the mappings that joysticks are looked up
+	/// in, which affects joysticks that connect afterwards
+	pub fn db_mut(&mut self) -> &mut MappingDb {
+		&mut self.db
+	}
+	/// Get the mapping used for the joystick with the index given
+	pub fn mapping(&self, index: JoystickIndex) -> Option<&Mapping> {
+		self.mappings.get(&index).or_else(|| self.found.get(&index))
+	}
+	/// Use the mapping given for the joystick with the index given, replacing
+	/// any mapping it already has, and for any joystick that connects with
+	/// that index later
+	pub fn set_mapping(&mut self, index: JoystickIndex, mapping: Mapping) {
+		self.mappings.insert(index, mapping);
+	}
+	/// Poll for the next gamepad event
+	pub fn poll(&mut self) -> Option<GamepadEvent> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Some(event)
+			}
+			match self.backend.poll() {
+				Some(event) => self.translate(event),
+				None => return None
+			}
+		}
+	}
+	/// Iterate through the gamepad events that are ready
+	pub fn iter(&mut self) -> GamepadEvents<B> {
+		GamepadEvents { gamepad: self }
+	}
+	/// Guess the mapping of a newly connected joystick if it doesn't have one
+	fn connect(&mut self, index: JoystickIndex) {
+		if self.mappings.contains_key(&index) || self.found.contains_key(&index) {
+			return
+		}
+		let mapping = self.backend.joysticks().iter()
+			.find(|js| js.index() == index)
+			.and_then(|js| match self.db.lookup(js) {
+				Some(mapping) => Some(mapping.clone()),
+				None => Mapping::from_capabilities(&js.id(), &js.capabilities())
+			});
+		if let Some(mapping) = mapping {
+			self.found.insert(index, mapping);
+		}
+	}
+	/// Queue the gamepad events for a joystick event
+	fn translate(&mut self, event: Event) {
+		match event {
+			Event::Connected(index) => {
+				self.connect(index);
+				self.pending.push_back(GamepadEvent::Connected(index));
+			},
+			Event::Disconnected(index) => {
+				self.pressed.retain(|&(i, _)| i != index);
+				self.hats.retain(|&(i, _), _| i != index);
+				// The next joystick with this index may be a different model
+				self.found.remove(&index);
+				self.pending.push_back(GamepadEvent::Disconnected(index));
+			},
+			Event::ButtonPressed(index, button) => self.drive(index, |source| match source {
+				Source::Button(b) if b == button => Some(Value::Half(1.0)),
+				_ => None
+			}),
+			Event::ButtonReleased(index, button) => self.drive(index, |source| match source {
+				Source::Button(b) if b == button => Some(Value::Half(0.0)),
+				_ => None
+			}),
+			Event::AxisMoved(index, axis, value) => self.drive(index, |source| match source {
+				Source::Axis(a, range, inverted) if a == axis => {
+					let value = if inverted { -value } else { value };
+					Some(match range {
+						AxisRange::Full => Value::Full(value),
+						AxisRange::Positive => Value::Half(value.max(0.0)),
+						AxisRange::Negative => Value::Half((-value).max(0.0))
+					})
+				},
+				_ => None
+			}),
+			Event::HatMoved(index, hat, pos) => {
+				let last = self.hats.insert((index, hat), pos).unwrap_or(HatPos::Centered);
+				self.drive(index, |source| match source {
+					// Only drive the targets of directions that changed
+					Source::Hat(h, dir) if h == hat && last.contains(dir) != pos.contains(dir) =>
+						Some(Value::Half(if pos.contains(dir) { 1.0 } else { 0.0 })),
+					_ => None
+				})
+			}
+		}
+	}
+	/// Drive the targets of every binding whose source has a value
+	fn drive<F>(&mut self, index: JoystickIndex, value_of: F) where F: Fn(Source) -> Option<Value> {
+		let found = &self.found;
+		let mapping = match self.mappings.get(&index).or_else(|| found.get(&index)) {
+			Some(mapping) => mapping,
+			None => return
+		};
+		for binding in &mapping.bindings {
+			let value = match value_of(binding.source) {
+				Some(value) => value,
+				None => continue
+			};
+			match binding.target {
+				Target::Button(button) => {
+					let pressed = value.magnitude() > 0.5;
+					if pressed && self.pressed.insert((index, button)) {
+						self.pending.push_back(GamepadEvent::ButtonPressed(index, button));
+					} else if !pressed && self.pressed.remove(&(index, button)) {
+						self.pending.push_back(GamepadEvent::ButtonReleased(index, button));
+					}
+				},
+				Target::Axis(axis, range) => {
+					let value = match (range, value) {
+						(AxisRange::Full, Value::Full(value)) if !axis.is_trigger() => value,
+						(AxisRange::Full, Value::Half(value)) if !axis.is_trigger() => value * 2.0 - 1.0,
+						(AxisRange::Negative, value) => -value.magnitude(),
+						(_, value) => value.magnitude()
+					};
+					self.pending.push_back(GamepadEvent::AxisMoved(index, axis, value));
+				}
+			}
+		}
+	}
+}
+
+/// An iterator over a gamepad's events.
+pub struct GamepadEvents<'a, B> where B: Backend + 'a {
+	gamepad: &'a mut Gamepad<B>
+}
+
+impl<'a, B> Iterator for GamepadEvents<'a, B> where B: Backend {
+	type Item = GamepadEvent;
+	/// This calls the `Gamepad.poll()` method to poll for the next event
+	fn next(&mut self) -> Option<GamepadEvent> {
+		self.gamepad.poll()
+	}
+}
@@ -0,0 +1,271 @@
+//! Recognition of taps, double taps, long presses, holds and chords from the
+//! timestamps of button events.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+use {Backend, Button, Event, JoystickIndex, TimedEvent, Timestamp};
+
+/// A gesture made with the buttons of a joystick.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Gesture {
+	/// Fired when a button is released soon after being pressed
+	Tap(JoystickIndex, Button),
+	/// Fired along with the second of two taps of a button close together
+	DoubleTap(JoystickIndex, Button),
+	/// Fired once when a button has been held down for long enough
+	LongPress(JoystickIndex, Button),
+	/// Fired repeatedly while a button is held down, with how long it has
+	/// been held for
+	Hold(JoystickIndex, Button, Timestamp),
+	/// Fired when every button of a chord is pressed close together, with
+	/// the buttons of the chord
+	Chord(JoystickIndex, Vec<Button>)
+}
+
+/// A gesture along with the time it was made at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimedGesture {
+	pub gesture: Gesture,
+	pub time: Timestamp
+}
+
+/// The times in milliseconds that gestures are recognized by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Thresholds {
+	/// The longest a button can be held down for and still be a tap
+	pub tap: Timestamp,
+	/// The longest gap between the taps of a double tap
+	pub double_tap: Timestamp,
+	/// How long a button must be held down for to be a long press
+	pub long_press: Timestamp,
+	/// How often to report how long a button has been held for
+	pub hold_interval: Timestamp,
+	/// The longest time between the first and last buttons of a chord being
+	/// pressed
+	pub chord_window: Timestamp
+}
+
+impl Default for Thresholds {
+	fn default() -> Thresholds {
+		Thresholds {
+			tap: 200,
+			double_tap: 300,
+			long_press: 500,
+			hold_interval: 100,
+			chord_window: 50
+		}
+	}
+}
+
+/// The state of a button that is held down
+struct Held {
+	since: Timestamp,
+	long_pressed: bool,
+	last_hold: Timestamp
+}
+
+/// Recognizes gestures from timed button events, which can come from any
+/// `Backend`.
+///
+/// Long presses and holds happen while nothing changes, so `tick` must be
+/// called regularly to recognize them.
+///
+/// ``` rust
+/// use reminisce::{Event, TimedEvent};
+/// use reminisce::gesture::{Gesture, Recognizer};
+///
+/// let mut recognizer = Recognizer::new();
+/// recognizer.add_chord(&[4, 5]);
+/// for &(event, time) in &[
+///     (Event::ButtonPressed(0, 1), 1000),
+///     (Event::ButtonReleased(0, 1), 1080),
+///     (Event::ButtonPressed(0, 1), 1200),
+///     (Event::ButtonReleased(0, 1), 1260),
+///     (Event::ButtonPressed(0, 4), 2000),
+///     (Event::ButtonPressed(0, 5), 2020)
+/// ] {
+///     recognizer.process(TimedEvent { event: event, time: time });
+/// }
+/// recognizer.tick_at(2600);
+/// let gestures: Vec<Gesture> = recognizer.iter().map(|gesture| gesture.gesture).collect();
+/// assert_eq!(&gestures[..5], &[
+///     Gesture::Tap(0, 1),
+///     Gesture::Tap(0, 1),
+///     Gesture::DoubleTap(0, 1),
+///     Gesture::Chord(0, vec![4, 5]),
+///     Gesture::LongPress(0, 4)
+/// ]);
+/// ```
+pub struct Recognizer {
+	thresholds: Thresholds,
+	chords: Vec<Vec<Button>>,
+	held: HashMap<(JoystickIndex, Button), Held>,
+	/// The time each button was last tapped, if it could start a double tap
+	last_tap: HashMap<(JoystickIndex, Button), Timestamp>,
+	/// The chords that have fired and not been let go of yet
+	fired: HashSet<(JoystickIndex, usize)>,
+	/// The time of the last event and when it was processed, for estimating
+	/// the backend's clock
+	last_seen: Option<(Timestamp, Instant)>,
+	pending: VecDeque<TimedGesture>
+}
+
+impl Default for Recognizer {
+	fn default() -> Recognizer {
+		Recognizer::new()
+	}
+}
+
+impl Recognizer {
+	/// Create a new recognizer with the default thresholds
+	pub fn new() -> Recognizer {
+		Recognizer::with_thresholds(Thresholds::default())
+	}
+	/// Create a new recognizer with the thresholds given
+	pub fn with_thresholds(thresholds: Thresholds) -> Recognizer {
+		Recognizer {
+			thresholds: thresholds,
+			chords: Vec::new(),
+			held: HashMap::new(),
+			last_tap: HashMap::new(),
+			fired: HashSet::new(),
+			last_seen: None,
+			pending: VecDeque::new()
+		}
+	}
+	/// Get the thresholds gestures are recognized by
+	pub fn thresholds(&self) -> Thresholds {
+		self.thresholds
+	}
+	/// Set the thresholds gestures are recognized by
+	pub fn set_thresholds(&mut self, thresholds: Thresholds) {
+		self.thresholds = thresholds;
+	}
+	/// Recognize the buttons given being pressed together as a chord
+	pub fn add_chord(&mut self, buttons: &[Button]) {
+		self.chords.push(buttons.to_vec());
+	}
+	/// Recognize gestures in a timed event
+	pub fn process(&mut self, event: TimedEvent) {
+		let time = event.time;
+		self.last_seen = Some((time, Instant::now()));
+		match event.event {
+			Event::ButtonPressed(index, button) => {
+				self.held.insert((index, button), Held { since: time, long_pressed: false, last_hold: time });
+				self.press_chords(index, time);
+			},
+			Event::ButtonReleased(index, button) => {
+				let chords = &self.chords;
+				self.fired.retain(|&(i, chord)| i != index || !chords[chord].contains(&button));
+				let key = (index, button);
+				let since = match self.held.remove(&key) {
+					Some(held) => held.since,
+					None => return
+				};
+				if time.wrapping_sub(since) > self.thresholds.tap {
+					self.last_tap.remove(&key);
+					return
+				}
+				self.push(Gesture::Tap(index, button), time);
+				match self.last_tap.remove(&key) {
+					Some(last) if since.wrapping_sub(last) <= self.thresholds.double_tap =>
+						self.push(Gesture::DoubleTap(index, button), time),
+					_ => {
+						self.last_tap.insert(key, time);
+					}
+				}
+			},
+			Event::Disconnected(index) => {
+				self.held.retain(|&(i, _), _| i != index);
+				self.last_tap.retain(|&(i, _), _| i != index);
+				self.fired.retain(|&(i, _)| i != index);
+			},
+			_ => ()
+		}
+		self.tick_at(time);
+	}
+	/// Fire the chords of a joystick whose buttons were all pressed close
+	/// together
+	fn press_chords(&mut self, index: JoystickIndex, time: Timestamp) {
+		for (number, chord) in self.chords.iter().enumerate() {
+			if self.fired.contains(&(index, number)) {
+				continue
+			}
+			let pressed: Option<Vec<Timestamp>> = chord.iter()
+				.map(|&button| self.held.get(&(index, button)).map(|held| held.since))
+				.collect();
+			let pressed = match pressed {
+				Some(ref pressed) if !pressed.is_empty() => pressed,
+				_ => continue
+			};
+			let first = pressed.iter().map(|&since| time.wrapping_sub(since)).max().unwrap_or(0);
+			if first <= self.thresholds.chord_window {
+				self.fired.insert((index, number));
+				self.pending.push_back(TimedGesture { gesture: Gesture::Chord(index, chord.clone()), time: time });
+			}
+		}
+	}
+	/// Recognize long presses and holds, estimating the backend's clock from
+	/// the time of the last event
+	pub fn tick(&mut self) {
+		if let Some((time, seen)) = self.last_seen {
+			let elapsed = seen.elapsed();
+			let elapsed = elapsed.as_secs() as u32 * 1000 + elapsed.subsec_nanos() / 1_000_000;
+			self.tick_at(time.wrapping_add(elapsed));
+		}
+	}
+	/// Recognize long presses and holds at the time given, which must be
+	/// from the clock of the backend the events came from
+	pub fn tick_at(&mut self, now: Timestamp) {
+		let thresholds = self.thresholds;
+		let mut gestures = Vec::new();
+		for (&(index, button), held) in &mut self.held {
+			let duration = now.wrapping_sub(held.since);
+			if duration > Timestamp::max_value() / 2 {
+				// This is from before the button was pressed
+				continue
+			}
+			if !held.long_pressed && duration >= thresholds.long_press {
+				held.long_pressed = true;
+				gestures.push((held.since.wrapping_add(thresholds.long_press), Gesture::LongPress(index, button)));
+			}
+			if now.wrapping_sub(held.last_hold) >= thresholds.hold_interval && thresholds.hold_interval > 0 {
+				held.last_hold = now;
+				gestures.push((now, Gesture::Hold(index, button, duration)));
+			}
+		}
+		gestures.sort_by_key(|&(time, _)| time);
+		for (time, gesture) in gestures {
+			self.push(gesture, time);
+		}
+	}
+	fn push(&mut self, gesture: Gesture, time: Timestamp) {
+		self.pending.push_back(TimedGesture { gesture: gesture, time: time });
+	}
+	/// Process every event that is ready from the backend given, then tick
+	pub fn update<B>(&mut self, backend: &mut B) where B: Backend {
+		while let Some(event) = backend.poll_timed() {
+			self.process(event);
+		}
+		self.tick();
+	}
+	/// Get the next gesture that was recognized
+	pub fn poll(&mut self) -> Option<TimedGesture> {
+		self.pending.pop_front()
+	}
+	/// Iterate through the gestures that were recognized
+	pub fn iter(&mut self) -> Gestures {
+		Gestures { recognizer: self }
+	}
+}
+
+/// An iterator over the gestures a `Recognizer` has recognized.
+pub struct Gestures<'a> {
+	recognizer: &'a mut Recognizer
+}
+
+impl<'a> Iterator for Gestures<'a> {
+	type Item = TimedGesture;
+	fn next(&mut self) -> Option<TimedGesture> {
+		self.recognizer.poll()
+	}
+}
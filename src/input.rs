@@ -0,0 +1,250 @@
+//! Named actions bound to joystick controls, so games can ask whether "Jump"
+//! was pressed this frame rather than watching for button 3.
+use std::collections::{HashMap, HashSet};
+use gamepad::AxisRange;
+use {Axis, Backend, Button, Event, Hat, HatPos, JoystickIndex};
+
+/// A control, or combination of controls, that can drive an action.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+	Button(Button),
+	/// An axis and the part of its range that is used
+	Axis(Axis, AxisRange),
+	/// A hat being held in a direction, including the diagonals next to it
+	Hat(Hat, HatPos),
+	/// Every one of the inputs being held together
+	Chord(Vec<Input>)
+}
+
+/// An input on one joystick, or on any joystick if no index is given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+	pub joystick: Option<JoystickIndex>,
+	pub input: Input
+}
+
+/// The last known state of a joystick's controls
+#[derive(Clone, Debug, Default)]
+struct Controls {
+	buttons: HashSet<Button>,
+	axes: HashMap<Axis, f32>,
+	hats: HashMap<Hat, HatPos>
+}
+
+impl Controls {
+	/// Get how far an input is pushed, with buttons and hats being `0` or `1`
+	fn value(&self, input: &Input, threshold: f32) -> f32 {
+		match *input {
+			Input::Button(button) => if self.buttons.contains(&button) { 1.0 } else { 0.0 },
+			Input::Axis(axis, range) => {
+				let value = self.axes.get(&axis).cloned().unwrap_or(0.0);
+				match range {
+					AxisRange::Full => value,
+					AxisRange::Positive => value.max(0.0),
+					AxisRange::Negative => (-value).max(0.0)
+				}
+			},
+			Input::Hat(hat, dir) => if self.hats.get(&hat).map_or(false, |pos| pos.contains(dir)) { 1.0 } else { 0.0 },
+			Input::Chord(ref inputs) => {
+				let held = inputs.iter().all(|input| self.value(input, threshold).abs() > threshold);
+				if held { 1.0 } else { 0.0 }
+			}
+		}
+	}
+}
+
+/// A set of actions bound to inputs, grouped into contexts of which one is
+/// active at a time, like one for menus and one for gameplay.
+///
+/// Call `update` once per frame to feed it the events from a backend, then
+/// query the actions of the active context. Digital actions are held while
+/// any of their inputs are pushed further than the threshold, which is `0.5`
+/// by default.
+///
+/// ``` rust
+/// use reminisce::Event;
+/// use reminisce::gamepad::AxisRange;
+/// use reminisce::input::{Input, InputMap};
+///
+/// let mut map = InputMap::new();
+/// map.bind("gameplay", "Jump", None, Input::Button(3));
+/// map.bind("gameplay", "MoveX", None, Input::Axis(0, AxisRange::Full));
+/// map.set_context("gameplay");
+///
+/// map.begin_frame();
+/// map.process(Event::ButtonPressed(0, 3));
+/// map.process(Event::AxisMoved(1, 0, -0.75));
+/// assert!(map.pressed("Jump"));
+/// assert_eq!(map.value("MoveX"), -0.75);
+///
+/// map.begin_frame();
+/// assert!(!map.pressed("Jump"));
+/// assert!(map.held("Jump"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct InputMap {
+	/// The bindings of each action in each context
+	contexts: HashMap<String, HashMap<String, Vec<Binding>>>,
+	context: Option<String>,
+	threshold: f32,
+	joysticks: HashMap<JoystickIndex, Controls>,
+	/// The value of each action of the active context that isn't at rest
+	values: HashMap<String, f32>,
+	pressed: HashSet<String>,
+	released: HashSet<String>
+}
+
+impl Default for InputMap {
+	fn default() -> InputMap {
+		InputMap::new()
+	}
+}
+
+impl InputMap {
+	/// Create a new input map without any contexts
+	pub fn new() -> InputMap {
+		InputMap {
+			contexts: HashMap::new(),
+			context: None,
+			threshold: 0.5,
+			joysticks: HashMap::new(),
+			values: HashMap::new(),
+			pressed: HashSet::new(),
+			released: HashSet::new()
+		}
+	}
+	/// Bind an action in a context to an input on the joystick given, or on
+	/// any joystick if none is given, alongside any inputs it is already
+	/// bound to
+	pub fn bind(&mut self, context: &str, action: &str, joystick: Option<JoystickIndex>, input: Input) {
+		self.contexts.entry(context.to_owned()).or_insert_with(HashMap::new)
+			.entry(action.to_owned()).or_insert_with(Vec::new)
+			.push(Binding { joystick: joystick, input: input });
+		self.refresh(false);
+	}
+	/// Remove every binding of an action in a context
+	pub fn unbind(&mut self, context: &str, action: &str) {
+		if let Some(actions) = self.contexts.get_mut(context) {
+			actions.remove(action);
+		}
+		self.refresh(false);
+	}
+	/// Get the bindings of an action in a context
+	pub fn bindings(&self, context: &str, action: &str) -> &[Binding] {
+		self.contexts.get(context)
+			.and_then(|actions| actions.get(action))
+			.map_or(&[], |bindings| &bindings[..])
+	}
+	/// Switch to the context given.
+	///
+	/// Actions of the new context that are already held don't count as
+	/// pressed, so switching doesn't trigger them.
+	pub fn set_context(&mut self, context: &str) {
+		self.context = Some(context.to_owned());
+		self.pressed.clear();
+		self.released.clear();
+		self.refresh(false);
+	}
+	/// Get the name of the active context
+	pub fn context(&self) -> Option<&str> {
+		self.context.as_ref().map(|context| &context[..])
+	}
+	/// Set how far an input must be pushed for a digital action to be held
+	pub fn set_threshold(&mut self, threshold: f32) {
+		self.threshold = threshold;
+		self.refresh(true);
+	}
+	/// Start a new frame, forgetting which actions were pressed and released
+	pub fn begin_frame(&mut self) {
+		self.pressed.clear();
+		self.released.clear();
+	}
+	/// Update the actions with an event
+	pub fn process(&mut self, event: Event) {
+		match event {
+			Event::Connected(_) => return,
+			Event::Disconnected(index) => {
+				self.joysticks.remove(&index);
+			},
+			Event::ButtonPressed(index, button) => {
+				self.joysticks.entry(index).or_insert_with(Controls::default).buttons.insert(button);
+			},
+			Event::ButtonReleased(index, button) => {
+				self.joysticks.entry(index).or_insert_with(Controls::default).buttons.remove(&button);
+			},
+			Event::AxisMoved(index, axis, value) => {
+				self.joysticks.entry(index).or_insert_with(Controls::default).axes.insert(axis, value);
+			},
+			Event::HatMoved(index, hat, pos) => {
+				self.joysticks.entry(index).or_insert_with(Controls::default).hats.insert(hat, pos);
+			}
+		}
+		self.refresh(true);
+	}
+	/// Start a new frame and process every event that is ready from the
+	/// backend given
+	pub fn update<B>(&mut self, backend: &mut B) where B: Backend {
+		self.begin_frame();
+		while let Some(event) = backend.poll() {
+			self.process(event);
+		}
+	}
+	/// Check if an action was pressed this frame
+	pub fn pressed(&self, action: &str) -> bool {
+		self.pressed.contains(action)
+	}
+	/// Check if an action was released this frame
+	pub fn released(&self, action: &str) -> bool {
+		self.released.contains(action)
+	}
+	/// Check if an action is being held
+	pub fn held(&self, action: &str) -> bool {
+		self.value(action).abs() > self.threshold
+	}
+	/// Get how far an action is pushed, which is the value of whichever of
+	/// its inputs is pushed the furthest
+	pub fn value(&self, action: &str) -> f32 {
+		self.values.get(action).cloned().unwrap_or(0.0)
+	}
+	/// Recompute the value of every action in the active context, noting
+	/// which were pressed or released if `edges` is set
+	fn refresh(&mut self, edges: bool) {
+		let contexts = &self.contexts;
+		let actions = match self.context.as_ref().and_then(|context| contexts.get(context)) {
+			Some(actions) => actions,
+			None => {
+				self.values.clear();
+				return
+			}
+		};
+		let mut values = HashMap::new();
+		for (action, bindings) in actions {
+			let mut value = 0.0f32;
+			for binding in bindings {
+				for (&index, controls) in &self.joysticks {
+					if binding.joystick.map_or(true, |joystick| joystick == index) {
+						let v = controls.value(&binding.input, self.threshold);
+						if v.abs() > value.abs() {
+							value = v;
+						}
+					}
+				}
+			}
+			if value != 0.0 {
+				values.insert(action.clone(), value);
+			}
+		}
+		if edges {
+			let threshold = self.threshold;
+			let held = |values: &HashMap<String, f32>, action: &String| values.get(action).map_or(false, |v| v.abs() > threshold);
+			for action in actions.keys() {
+				match (held(&self.values, action), held(&values, action)) {
+					(false, true) => { self.pressed.insert(action.clone()); },
+					(true, false) => { self.released.insert(action.clone()); },
+					_ => ()
+				}
+			}
+		}
+		self.values = values;
+	}
+}
@@ -1,140 +1,569 @@
-use libc::{c_char, c_ulong, c_int, c_uint, O_RDONLY, O_NONBLOCK, read};
-use inotify::INotify;
-use inotify::ffi;
-use glob::glob;
+use libc::{c_char, c_int, c_uint, stat, timespec, clock_gettime, fstat, CLOCK_MONOTONIC};
 use std::borrow::Cow;
-use std::ffi::{CStr, CString};
-use std::io::Error;
+use std::collections::VecDeque;
+use std::fs;
+use std::ffi::CStr;
+use std::io::{Error, ErrorKind};
 use std::mem;
-use std::path::Path;
-use std::str::FromStr;
-use {Backend, Event, Joystick};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use calibration::{Correction, JsCorr};
+use devices::{open_device, read_record, Device, Devices, Record};
+use {AxisInfo, Backend, BackendError, ButtonInfo, Capabilities, DeviceId, Event, Hat, HatPos, JoystickState,
+	TimedEvent, Timestamp};
 
 const JSIOCGAXES: c_uint = 2147576337;
 const JSIOCGBUTTONS: c_uint = 2147576338;
 const JSIOCGID: c_uint = 2151705107;
 const JSIOCGID_LEN: usize = 64;
+const JSIOCGAXMAP: c_uint = 2151705138;
+const JSIOCGBTNMAP: c_uint = 2214619700;
+const JSIOCGCORR: c_uint = 2149870114;
+const JSIOCSCORR: c_uint = 1076128289;
+const ABS_CNT: usize = 0x40;
+const KEY_MAX: usize = 0x2ff;
+const BTN_MISC: usize = 0x100;
+const ABS_HAT0X: u8 = 0x10;
+const ABS_HAT3Y: u8 = 0x17;
+const JS_EVENT_INIT: u8 = 0x80;
 
 extern {
-	fn open(path: *const c_char, oflag: c_int) -> c_int;
 	fn close(fd: c_int) -> c_int;
-	fn ioctl(fd: c_uint, op: c_uint, result: *mut c_char);
+	fn ioctl(fd: c_uint, op: c_uint, result: *mut c_char) -> c_int;
 }
 
 pub struct Native {
-	joysticks: Vec<NativeJoystick>,
-	pending: Vec<Event>,
-	inotify: INotify
+	devices: Devices<NativeJoystick>
 }
 impl Native {
-	fn inner_poll(&mut self) -> Option<Event> {
-		self.inotify.available_events().unwrap().iter()
-			.find(|e| (e.is_create() || e.is_delete()) && e.name.starts_with("js"))
-			.map(|e| {
-				let index = FromStr::from_str(&e.name[2..]).unwrap();
-				if e.is_create() {
-					Event::Connected(index)
-				} else {
-					Event::Disconnected(index)
-				}
-			})
-			.or_else(||
-				self.pending.pop().or_else(|| 
-					self.joysticks.iter_mut().flat_map(|js| js.poll()).next()
-				)
-			)
+	/// Create a new backend that scans `root` instead of `/dev/input` for
+	/// `js*` devices.
+	///
+	/// Any file or FIFO speaking the joystick protocol can stand in for a
+	/// device, so hotplugging, reading and disconnecting can be exercised
+	/// without any hardware.
+	///
+	/// ``` rust
+	/// use reminisce::{Backend, Event};
+	/// use reminisce::linux::Native;
+	/// use std::fs::{self, File};
+	/// use std::io::Write;
+	///
+	/// let root = std::env::temp_dir().join("reminisce-linux-with-root");
+	/// fs::create_dir_all(&root).unwrap();
+	/// // A timestamp, then a value of 1 for the button press of button 3
+	/// let mut record = Vec::new();
+	/// record.extend(&0u32.to_ne_bytes());
+	/// record.extend(&1i16.to_ne_bytes());
+	/// record.extend(&[1u8, 3]);
+	/// File::create(root.join("js0")).unwrap().write_all(&record).unwrap();
+	///
+	/// let mut backend = Native::with_root(&root).unwrap();
+	/// assert_eq!(backend.poll(), Some(Event::Connected(0)));
+	/// assert_eq!(backend.poll(), Some(Event::ButtonPressed(0, 3)));
+	/// assert_eq!(backend.poll(), None);
+	/// fs::remove_file(root.join("js0")).unwrap();
+	/// assert_eq!(backend.poll(), Some(Event::Disconnected(0)));
+	/// # drop(backend);
+	/// # fs::remove_dir_all(&root).unwrap();
+	/// ```
+	///
+	/// A FIFO is disconnected once its writer closes it.
+	///
+	/// ``` rust
+	/// use reminisce::{Backend, Event};
+	/// use reminisce::linux::Native;
+	/// use std::fs::{self, OpenOptions};
+	/// use std::process::Command;
+	///
+	/// let root = std::env::temp_dir().join("reminisce-linux-fifo");
+	/// # let _ = fs::remove_dir_all(&root);
+	/// fs::create_dir_all(&root).unwrap();
+	/// assert!(Command::new("mkfifo").arg(root.join("js0")).status().unwrap().success());
+	///
+	/// let mut backend = Native::with_root(&root).unwrap();
+	/// let writer = OpenOptions::new().write(true).open(root.join("js0")).unwrap();
+	/// assert_eq!(backend.poll(), Some(Event::Connected(0)));
+	/// assert_eq!(backend.poll(), None);
+	/// drop(writer);
+	/// assert_eq!(backend.poll(), Some(Event::Disconnected(0)));
+	/// assert_eq!(backend.num_joysticks(), 0);
+	/// # drop(backend);
+	/// # fs::remove_dir_all(&root).unwrap();
+	/// ```
+	pub fn with_root<P>(root: P) -> Result<Native, BackendError> where P: AsRef<Path> {
+		Devices::open(root).map(|devices| Native { devices: devices })
+	}
+	/// Set whether each `Event::Connected` is followed by the events that
+	/// would bring the joystick from rest into its initial state, so held
+	/// buttons and resting axes can be seen without querying the joystick.
+	///
+	/// This is off by default.
+	///
+	/// ``` rust
+	/// use reminisce::{Backend, Event};
+	/// use reminisce::linux::Native;
+	/// use std::fs::{self, File};
+	/// use std::io::Write;
+	///
+	/// let root = std::env::temp_dir().join("reminisce-linux-initial-events");
+	/// fs::create_dir_all(&root).unwrap();
+	/// // The driver reports button 1 as already held with JS_EVENT_INIT set
+	/// let mut record = Vec::new();
+	/// record.extend(&0u32.to_ne_bytes());
+	/// record.extend(&1i16.to_ne_bytes());
+	/// record.extend(&[0x81u8, 1]);
+	/// File::create(root.join("js0")).unwrap().write_all(&record).unwrap();
+	///
+	/// let mut backend = Native::with_root(&root).unwrap();
+	/// backend.set_initial_events(true);
+	/// assert_eq!(backend.poll(), Some(Event::Connected(0)));
+	/// assert_eq!(backend.poll(), Some(Event::ButtonPressed(0, 1)));
+	/// assert_eq!(backend.poll(), None);
+	/// # drop(backend);
+	/// # fs::remove_dir_all(&root).unwrap();
+	/// ```
+	pub fn set_initial_events(&mut self, enabled: bool) {
+		self.devices.set_initial_events(enabled);
+	}
+	/// Set whether the axes that hats are made from are reported as well as
+	/// the hats.
+	///
+	/// The driver reports D-pads as a pair of axes, which are turned into
+	/// `Event::HatMoved` using the kernel's axis map. This is off by default,
+	/// so those axes never move.
+	pub fn set_hat_axes(&mut self, enabled: bool) {
+		self.devices.set_options(enabled);
+		for js in self.devices.joysticks_mut() {
+			js.set_hat_axes(enabled);
+		}
+	}
+	/// Read every event that is ready, so the file descriptor returned by
+	/// `as_raw_fd` won't be readable again until something new happens.
+	///
+	/// Call this whenever an external event loop reports that descriptor as
+	/// readable. This stops at the first error, which is returned unless
+	/// events were read before it. Either way, call this again until it
+	/// returns no events, since a joystick that failed is then reported as
+	/// disconnected.
+	///
+	/// ``` rust
+	/// use reminisce::Event;
+	/// use reminisce::linux::Native;
+	/// use std::fs::{self, File};
+	/// use std::io::Write;
+	///
+	/// let root = std::env::temp_dir().join("reminisce-linux-drain");
+	/// fs::create_dir_all(&root).unwrap();
+	/// let mut record = Vec::new();
+	/// record.extend(&0u32.to_ne_bytes());
+	/// record.extend(&1i16.to_ne_bytes());
+	/// record.extend(&[1u8, 3]);
+	/// File::create(root.join("js0")).unwrap().write_all(&record).unwrap();
+	///
+	/// let mut backend = Native::with_root(&root).unwrap();
+	/// let events: Vec<Event> = backend.drain().unwrap().iter().map(|event| event.event).collect();
+	/// assert_eq!(events, vec![Event::Connected(0), Event::ButtonPressed(0, 3)]);
+	/// assert!(backend.drain().unwrap().is_empty());
+	/// # drop(backend);
+	/// # fs::remove_dir_all(&root).unwrap();
+	/// ```
+	pub fn drain(&mut self) -> Result<Vec<TimedEvent>, BackendError> {
+		self.devices.drain()
 	}
 }
-impl Drop for Native {
-	fn drop(&mut self) {
-		let mut fresh = unsafe { mem::uninitialized() };
-		mem::swap(&mut self.inotify, &mut fresh);
-		fresh.close().unwrap();
+/// The file descriptor becomes readable whenever a joystick has input or a
+/// device is plugged in or unplugged, so this backend can be registered with
+/// an external event loop like mio and drained with `drain` on readiness.
+///
+/// Joysticks backed by regular files can't be waited on, so they never make
+/// it readable.
+impl AsRawFd for Native {
+	fn as_raw_fd(&self) -> RawFd {
+		self.devices.as_raw_fd()
 	}
 }
 impl Backend for Native {
 	type Joystick = NativeJoystick;
-	fn new() -> Native {
-		let mut joysticks = Vec::with_capacity(4);
-		for entry in glob("/dev/input/js*").unwrap() {
-			if let Ok(path) = entry {
-				if let Some(name) = path.file_name() {
-					if let Some(name) = name.to_str() {
-						if name.starts_with("js") {
-							if let Ok(index) = name[2..].parse() {
-								if let Ok(js) = Joystick::open(index) {
-									joysticks.push(js)
-								}
-							}
-						}
-					}
+	fn try_new() -> Result<Native, BackendError> {
+		Native::with_root("/dev/input")
+	}
+	fn joysticks(&self) -> &[NativeJoystick] {
+		self.devices.joysticks()
+	}
+	fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		self.devices.try_poll_timed()
+	}
+	/// Sleep on the joysticks and the device directory until an event arrives
+	/// or the timeout elapses
+	fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+		self.devices.try_wait_timed(timeout)
+	}
+}
+
+/// Get the current time on the clock the joystick driver stamps events with
+///
+/// The driver uses jiffies, which start five minutes before boot so that
+/// wrapping bugs show up early, converted to milliseconds.
+fn now() -> Timestamp {
+	let mut time: timespec = unsafe { mem::zeroed() };
+	unsafe {
+		clock_gettime(CLOCK_MONOTONIC, &mut time);
+	}
+	let millis = time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000;
+	(millis as u32).wrapping_sub(300_000)
+}
+
+/// A hat synthesized from a pair of `ABS_HAT*` axes
+struct HatAxes {
+	number: u8,
+	x: i16,
+	y: i16
+}
+
+impl HatAxes {
+	fn pos(&self) -> HatPos {
+		let direction = |value: i16| if value < -::MAX_AXIS_VALUE / 2 {
+			-1
+		} else if value > ::MAX_AXIS_VALUE / 2 {
+			1
+		} else {
+			0
+		};
+		HatPos::from_xy(direction(self.x), direction(self.y))
+	}
+}
+
+/// Represents a system joystick
+///
+/// When the driver's buffer overflows it sends the state of every control
+/// again, and whatever changed in the meantime is reported as events.
+///
+/// ``` rust
+/// use reminisce::{Backend, Event};
+/// use reminisce::linux::Native;
+/// use std::fs::{self, File};
+/// use std::io::Write;
+///
+/// let root = std::env::temp_dir().join("reminisce-linux-resync");
+/// fs::create_dir_all(&root).unwrap();
+/// let mut records = Vec::new();
+/// // A timestamp, a value, an event type and a button or axis number
+/// for &(value, kind, number) in &[
+///     (1i16, 0x01u8, 0u8),
+///     // The state of every control again, flagged with JS_EVENT_INIT
+///     (0, 0x81, 0),
+///     (1, 0x81, 1),
+///     (32767, 0x82, 0),
+///     (1, 0x01, 2)
+/// ] {
+///     records.extend(&0u32.to_ne_bytes());
+///     records.extend(&value.to_ne_bytes());
+///     records.extend(&[kind, number]);
+/// }
+/// File::create(root.join("js0")).unwrap().write_all(&records).unwrap();
+///
+/// let mut backend = Native::with_root(&root).unwrap();
+/// let events: Vec<Event> = backend.iter().collect();
+/// assert_eq!(events, vec![
+///     Event::Connected(0),
+///     Event::ButtonPressed(0, 0),
+///     Event::ButtonReleased(0, 0),
+///     Event::ButtonPressed(0, 1),
+///     Event::AxisMoved(0, 0, 1.0),
+///     Event::ButtonPressed(0, 2)
+/// ]);
+/// # drop(backend);
+/// # fs::remove_dir_all(&root).unwrap();
+/// ```
+pub struct NativeJoystick {
+	index: u8,
+	fd: c_int,
+	/// The hat each axis is part of, if any, and whether it is the hat's
+	/// horizontal axis
+	axis_hats: Vec<Option<(Hat, bool)>>,
+	hats: Vec<HatAxes>,
+	/// Whether the axes of hats are reported as well as the hats
+	hat_axes: bool,
+	/// The state reported by the driver when the joystick was opened
+	initial: JoystickState,
+	/// The state of the joystick as reported by the events so far
+	state: JoystickState,
+	/// An event that was read while reading the state of every control
+	queued: Option<LinuxEvent>,
+	/// A hat event to report after the axis event it was made from
+	queued_hat: Option<TimedEvent>,
+	/// The events that bring the state back in line after an overflow
+	resynced: VecDeque<TimedEvent>
+}
+
+impl NativeJoystick {
+	/// Open the joystick device at `path` as the joystick with the index given.
+	///
+	/// ``` rust
+	/// use reminisce::linux::NativeJoystick;
+	/// use std::io::ErrorKind;
+	/// let error = NativeJoystick::open_at("/dev/input/js\0", 0).err().unwrap();
+	/// assert_eq!(error.kind(), ErrorKind::InvalidInput);
+	/// ```
+	pub fn open_at<P>(path: P, index: u8) -> Result<NativeJoystick, Error> where P: AsRef<Path> {
+		let fd = try!(open_device(path.as_ref()));
+		let mut joystick = NativeJoystick {
+			index: index,
+			fd: fd,
+			axis_hats: Vec::new(),
+			hats: Vec::new(),
+			hat_axes: false,
+			initial: JoystickState::new(0, 0, 0),
+			state: JoystickState::new(0, 0, 0),
+			queued: None,
+			queued_hat: None,
+			resynced: VecDeque::new()
+		};
+		let mut axis_map = [0u8; ABS_CNT];
+		if unsafe { ioctl(fd as u32, JSIOCGAXMAP, axis_map.as_mut_ptr() as *mut c_char) } != -1 {
+			let num_axes = ::Joystick::num_axes(&joystick) as usize;
+			let mut numbers: Vec<u8> = axis_map.iter().take(num_axes).cloned()
+				.filter(|&code| code >= ABS_HAT0X && code <= ABS_HAT3Y)
+				.map(|code| (code - ABS_HAT0X) / 2)
+				.collect();
+			numbers.sort();
+			numbers.dedup();
+			joystick.hats = numbers.into_iter().map(|number| HatAxes { number: number, x: 0, y: 0 }).collect();
+			joystick.axis_hats = axis_map.iter().take(num_axes).map(|&code| {
+				if code < ABS_HAT0X || code > ABS_HAT3Y {
+					return None
 				}
-			}
-		}
-		let pending = joysticks.iter().by_ref().map(|js:&NativeJoystick| Event::Connected(js.index)).collect();
-		let inotify = INotify::init().unwrap();
-		inotify.add_watch(Path::new("/dev/input"), ffi::IN_CREATE | ffi::IN_DELETE).unwrap();
-		Native {
-			joysticks: joysticks,
-			pending: pending,
-			inotify: inotify
+				let number = (code - ABS_HAT0X) / 2;
+				let hat = joystick.hats.iter().position(|hat| hat.number == number).unwrap();
+				Some((hat as Hat, (code - ABS_HAT0X) % 2 == 0))
+			}).collect();
 		}
+		joystick.state = JoystickState::new(::Joystick::num_buttons(&joystick), ::Joystick::num_axes(&joystick),
+			joystick.hats.len() as Hat);
+		joystick.state.time = now();
+		// The driver starts by sending the state of every button and axis as
+		// events flagged with JS_EVENT_INIT
+		joystick.read_init();
+		joystick.initial = joystick.state.clone();
+		Ok(joystick)
 	}
-	fn num_joysticks(&self) -> usize {
-		return self.joysticks.len();
+	/// Set whether the axes that hats are made from are reported as well as
+	/// the hats
+	pub fn set_hat_axes(&mut self, enabled: bool) {
+		self.hat_axes = enabled;
 	}
-	fn joysticks(&self) -> &[NativeJoystick] {
-		return &self.joysticks;
-	}
-	fn poll(&mut self) -> Option<Event> {
-		match self.inner_poll() {
-			Some(Event::Connected(index)) => {
-				if let Ok(joystick) = NativeJoystick::open(index) {
-					self.joysticks.push(joystick);
-					Some(Event::Connected(index))
-				} else {
-					self.inner_poll()
+	/// Update the state with a button or axis event, with or without
+	/// JS_EVENT_INIT set
+	fn apply(&mut self, event: &LinuxEvent) {
+		let number = event.number as usize;
+		match event._type & !JS_EVENT_INIT {
+			1 => {
+				if number >= self.state.buttons.len() {
+					self.state.buttons.resize(number + 1, false);
 				}
+				self.state.buttons[number] = event.value != 0;
 			},
-			Some(Event::Disconnected(index)) => {
-				let (arr_index, _) = self.joysticks.iter().map(Joystick::index).enumerate().find(|&(_, i)| i == index).unwrap();
-				self.joysticks.remove(arr_index);
-				Some(Event::Disconnected(arr_index as u8))
+			2 => {
+				if number >= self.state.axes.len() {
+					self.state.axes.resize(number + 1, 0.0);
+				}
+				self.state.axes[number] = event.value as f32 / ::MAX_AXIS_VALUE as f32;
+				if let Some(hat) = self.move_hat(event.number, event.value) {
+					self.state.hats[hat as usize] = self.hats[hat as usize].pos();
+				}
 			},
-			v => v
+			_ => ()
 		}
 	}
-}
-/// Represents a system joystick
-pub struct NativeJoystick {
-	index: u8,
-	fd: c_int
+	/// Apply every event flagged with JS_EVENT_INIT that is ready, keeping
+	/// the first one that isn't for later
+	fn read_init(&mut self) {
+		while let Ok(Record::Read(event)) = self.read() {
+			if event._type & JS_EVENT_INIT == 0 {
+				self.queued = Some(event);
+				return
+			}
+			self.apply(&event);
+		}
+	}
+	/// Update the hat an axis is part of, if any, returning the hat
+	fn move_hat(&mut self, axis: u8, value: i16) -> Option<Hat> {
+		let (hat, horizontal) = match self.axis_hats.get(axis as usize) {
+			Some(&Some(part)) => part,
+			_ => return None
+		};
+		if horizontal {
+			self.hats[hat as usize].x = value;
+		} else {
+			self.hats[hat as usize].y = value;
+		}
+		Some(hat)
+	}
+	/// Update the hat an axis event is part of, if any, returning the event
+	/// for the hat if its position changed
+	fn hat_event(&mut self, event: LinuxEvent) -> Option<TimedEvent> {
+		let hat = match self.axis_hats.get(event.number as usize) {
+			Some(&Some((hat, _))) => hat,
+			_ => return None
+		};
+		let last = self.hats[hat as usize].pos();
+		self.move_hat(event.number, event.value);
+		let pos = self.hats[hat as usize].pos();
+		if pos == last {
+			return None
+		}
+		Some(TimedEvent {
+			event: Event::HatMoved(self.index, hat, pos),
+			time: event.time
+		})
+	}
+	/// Get how the driver corrects the raw values of each axis
+	pub fn correction(&self) -> Result<Vec<Correction>, Error> {
+		let num_axes = ::Joystick::num_axes(self) as usize;
+		let mut raw = vec![Correction::None.to_raw(); num_axes.max(1)];
+		if unsafe { ioctl(self.fd as u32, JSIOCGCORR, raw.as_mut_ptr() as *mut c_char) } == -1 {
+			return Err(Error::last_os_error())
+		}
+		raw.iter().take(num_axes).map(Correction::from_raw).collect()
+	}
+	/// Set how the driver corrects the raw values of each axis.
+	///
+	/// There must be a correction for every axis. Use `Correction::None` on
+	/// every axis to read raw values for calibrating, then compute new
+	/// corrections with `Correction::from_range`.
+	///
+	/// ``` rust,no_run
+	/// use reminisce::calibration::{self, Correction};
+	/// use reminisce::linux::NativeJoystick;
+	/// let mut joystick = NativeJoystick::open_at("/dev/input/js0", 0).unwrap();
+	/// let corrections = vec![Correction::from_range(0, 128, 255); 2];
+	/// joystick.set_correction(&corrections).unwrap();
+	/// calibration::save("js0.cal", &joystick.correction().unwrap()).unwrap();
+	/// ```
+	pub fn set_correction(&mut self, corrections: &[Correction]) -> Result<(), Error> {
+		if corrections.len() != ::Joystick::num_axes(self) as usize {
+			return Err(Error::new(ErrorKind::InvalidInput, "there must be a correction for every axis"))
+		}
+		let mut raw: Vec<JsCorr> = corrections.iter().map(Correction::to_raw).collect();
+		if unsafe { ioctl(self.fd as u32, JSIOCSCORR, raw.as_mut_ptr() as *mut c_char) } == -1 {
+			return Err(Error::last_os_error())
+		}
+		Ok(())
+	}
+	/// Read the next raw event from the device, if there is one
+	fn read(&mut self) -> Result<Record<LinuxEvent>, Error> {
+		match self.queued.take() {
+			Some(event) => Ok(Record::Read(event)),
+			None => read_record(self.fd)
+		}
+	}
+	/// Get the sysfs directory of the input device behind the joystick
+	fn sysfs_dir(&self) -> Option<PathBuf> {
+		let mut info: stat = unsafe { mem::zeroed() };
+		if unsafe { fstat(self.fd, &mut info) } == -1 || info.st_rdev == 0 {
+			return None
+		}
+		let dev = info.st_rdev as u64;
+		let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+		let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+		Some(PathBuf::from(format!("/sys/dev/char/{}:{}/device", major, minor)))
+	}
+	/// Check whether the input device behind the joystick has any force
+	/// feedback effects, which sysfs lists as a bitmask in hex words
+	fn force_feedback(&self) -> bool {
+		self.sysfs_dir()
+			.and_then(|dir| fs::read_to_string(dir.join("capabilities/ff")).ok())
+			.map_or(false, |bits| bits.chars().any(|c| c.is_digit(16) && c != '0'))
+	}
 }
 
-impl NativeJoystick {
-	fn poll(&mut self) -> Option<Event> {
-		unsafe {
-			let mut event:LinuxEvent = mem::uninitialized();
-			loop {
-				let event_size = mem::size_of::<LinuxEvent>() as c_ulong;
-				if read(self.fd, mem::transmute(&mut event), event_size as usize) == -1 {
-					let err = Error::last_os_error();
-					match Error::last_os_error().raw_os_error().expect("Bad OS Error") {
-						11 => (),
-						19 => return Some(Event::Disconnected(self.index)),
-						_ => panic!("{}", err)
-					}
-				} else if event._type & 0x80 == 0 {
-					return Some(match (event._type, event.value) {
-						(1, 0) => Event::ButtonReleased(self.index, event.number),
-						(1, 1) => Event::ButtonPressed(self.index, event.number),
-						(2, _) => Event::AxisMoved(self.index, event.number, event.value as f32 / ::MAX_AXIS_VALUE as f32),
-						_ => panic!("Bad type and value {} {} for joystick", event._type, event.value)
+impl Device for NativeJoystick {
+	/// Whether the axes of hats are reported
+	type Options = bool;
+	fn prefix() -> &'static str {
+		"js"
+	}
+	fn open_with(path: &Path, index: u8, hat_axes: bool) -> Result<NativeJoystick, Error> {
+		let mut joystick = try!(NativeJoystick::open_at(path, index));
+		joystick.set_hat_axes(hat_axes);
+		Ok(joystick)
+	}
+	fn fd(&self) -> c_int {
+		self.fd
+	}
+	fn poll(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		if let Some(event) = self.queued_hat.take() {
+			return Ok(Some(event))
+		}
+		if let Some(event) = self.resynced.pop_front() {
+			return Ok(Some(event))
+		}
+		loop {
+			let event = match self.read() {
+				Ok(Record::Read(event)) => event,
+				Ok(Record::Empty) => return Ok(None),
+				Ok(Record::Closed) => return Ok(Some(TimedEvent {
+					event: Event::Disconnected(self.index),
+					time: now()
+				})),
+				Err(err) => return Err(BackendError::Io(err))
+			};
+			if event._type & JS_EVENT_INIT != 0 {
+				// The driver's buffer overflowed and it is sending the state
+				// of every control again, so report whatever changed
+				let before = self.state.clone();
+				self.apply(&event);
+				self.read_init();
+				let index = self.index;
+				let hat_axes = self.hat_axes;
+				let axis_hats = &self.axis_hats;
+				let hidden = |axis: u8| !hat_axes && axis_hats.get(axis as usize).map_or(false, |part| part.is_some());
+				self.resynced.extend(before.diff(&self.state, index).into_iter()
+					.filter(|changed| match *changed {
+						Event::AxisMoved(_, axis, _) => !hidden(axis),
+						_ => true
 					})
+					.map(|changed| TimedEvent { event: changed, time: event.time }));
+				if let Some(event) = self.resynced.pop_front() {
+					return Ok(Some(event))
 				}
+			} else {
+				let translated = match (event._type, event.value) {
+					(1, 0) => {
+						self.apply(&event);
+						Event::ButtonReleased(self.index, event.number)
+					},
+					(1, 1) => {
+						self.apply(&event);
+						Event::ButtonPressed(self.index, event.number)
+					},
+					(2, _) => {
+						let is_hat = self.axis_hats.get(event.number as usize).map_or(false, |part| part.is_some());
+						let moved = self.hat_event(event);
+						self.apply(&event);
+						if is_hat && !self.hat_axes {
+							match moved {
+								Some(moved) => return Ok(Some(moved)),
+								None => continue
+							}
+						}
+						self.queued_hat = moved;
+						Event::AxisMoved(self.index, event.number, event.value as f32 / ::MAX_AXIS_VALUE as f32)
+					},
+					_ => return Err(BackendError::UnknownEvent(self.index))
+				};
+				return Ok(Some(TimedEvent {
+					event: translated,
+					time: event.time
+				}))
 			}
 		}
 	}
+	fn now() -> Timestamp {
+		now()
+	}
 }
 
 impl ::Joystick for NativeJoystick {
@@ -142,74 +571,110 @@ impl ::Joystick for NativeJoystick {
 	/// This tries to open the interface `/dev/input/js...` and will return the
 	/// OS-level error if it fails to open this
 	fn open(index: u8) -> Result<NativeJoystick, Error> {
-		let path = format!("/dev/input/js{}", index);
-		unsafe {
-			let c_path = CString::new(path.as_bytes()).unwrap();
-			let fd = open(c_path.as_ptr(), O_RDONLY | O_NONBLOCK);
-			if fd == -1 {
-				Err(Error::last_os_error())
-			} else {
-				Ok(NativeJoystick {
-					index: index,
-					fd: fd
-				})
-			}
-		}
+		NativeJoystick::open_at(format!("/dev/input/js{}", index), index)
 	}
 	fn connected(&self) -> bool {
 		true
 	}
 	fn num_hats(&self) -> u8 {
-		0
+		self.hats.len() as u8
 	}
 	fn num_axes(&self) -> u8 {
 		unsafe {
-			let mut num_axes: c_char = mem::uninitialized();
+			let mut num_axes: c_char = 0;
 			ioctl(self.fd as u32, JSIOCGAXES, &mut num_axes as *mut i8);
 			num_axes as u8
 		}
 	}
 	fn num_buttons(&self) -> u8 {
 		unsafe {
-			let mut num_buttons: c_char = mem::uninitialized();
+			let mut num_buttons: c_char = 0;
 			ioctl(self.fd as u32, JSIOCGBUTTONS, &mut num_buttons as *mut i8);
 			num_buttons as u8
 		}
 	}
 	fn id(&self) -> Cow<str> {
 		unsafe {
-			let text = String::with_capacity(JSIOCGID_LEN);
-			ioctl(self.fd as u32, JSIOCGID, text.as_ptr() as *mut i8);
-			let mut new_text = String::from_raw_parts(text.as_ptr() as *mut u8, JSIOCGID_LEN, JSIOCGID_LEN);
-			let length = CStr::from_ptr(text.as_ptr() as *const i8).to_bytes().len();
-			mem::forget(text);
-			new_text.truncate(length);
-			new_text.shrink_to_fit();
-			new_text.into()
+			let mut text = [0u8; JSIOCGID_LEN];
+			ioctl(self.fd as u32, JSIOCGID, text.as_mut_ptr() as *mut i8);
+			text[JSIOCGID_LEN - 1] = 0;
+			CStr::from_ptr(text.as_ptr() as *const i8).to_string_lossy().into_owned().into()
 		}
 	}
 	fn index(&self) -> u8 {
 		self.index
 	}
+	/// This reads the IDs of the input device behind the joystick from sysfs
+	fn device_id(&self) -> Option<DeviceId> {
+		let dir = match self.sysfs_dir() {
+			Some(dir) => dir.join("id"),
+			None => return None
+		};
+		let read_id = |name: &str| fs::read_to_string(dir.join(name)).ok()
+			.and_then(|text| u16::from_str_radix(text.trim(), 16).ok());
+		match (read_id("bustype"), read_id("vendor"), read_id("product"), read_id("version")) {
+			(Some(bus), Some(vendor), Some(product), Some(version)) =>
+				Some(DeviceId::new(bus, vendor, product, version, &::Joystick::id(self))),
+			_ => None
+		}
+	}
 	/// This is not supported on Linux so None is returned every time
 	fn battery(&self) -> Option<f32> {
 		None
 	}
+	/// The axes of hats are at rest unless they are reported
+	fn initial_state(&self) -> Option<JoystickState> {
+		let mut state = self.initial.clone();
+		if !self.hat_axes {
+			for (axis, part) in self.axis_hats.iter().enumerate() {
+				if part.is_some() && axis < state.axes.len() {
+					state.axes[axis] = 0.0;
+				}
+			}
+		}
+		Some(state)
+	}
+	/// The joystick interface reports the kernel code of every control, and
+	/// scales every axis to the range `-32767` to `32767`.
+	///
+	/// Force feedback is read from the capabilities of the input device in
+	/// sysfs, but can only be used through its `eventN` device.
+	fn capabilities(&self) -> Capabilities {
+		let num_axes = ::Joystick::num_axes(self) as usize;
+		let num_buttons = ::Joystick::num_buttons(self) as usize;
+		let mut axis_map = [0u8; ABS_CNT];
+		let mut button_map = [0u16; KEY_MAX - BTN_MISC + 1];
+		let known = unsafe {
+			ioctl(self.fd as u32, JSIOCGAXMAP, axis_map.as_mut_ptr() as *mut c_char) != -1 &&
+			ioctl(self.fd as u32, JSIOCGBTNMAP, button_map.as_mut_ptr() as *mut c_char) != -1
+		};
+		Capabilities {
+			axes: axis_map.iter().take(num_axes).map(|&code| AxisInfo {
+				code: if known { Some(code as u16) } else { None },
+				min: -(::MAX_AXIS_VALUE as i32),
+				max: ::MAX_AXIS_VALUE as i32
+			}).collect(),
+			buttons: button_map.iter().take(num_buttons).map(|&code| ButtonInfo {
+				code: if known { Some(code) } else { None }
+			}).collect(),
+			hats: self.hats.len() as u8,
+			force_feedback: self.force_feedback(),
+			battery: false
+		}
+	}
 }
 
 impl Drop for NativeJoystick {
 	/// Close the joystick's file descriptor
 	fn drop(&mut self) {
 		unsafe {
-			if close(self.fd) == -1 {
-				let error = Error::last_os_error();
-				panic!("Failed to close joystick {} due to {}", self.index, error)
-			}
+			close(self.fd);
 		}
 	}
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct LinuxEvent {
 	/// timestamp in milleseconds
 	time: u32,
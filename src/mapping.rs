@@ -0,0 +1,288 @@
+//! Gamepad mappings in the format of SDL's community `gamecontrollerdb.txt`.
+//!
+//! Each line holds the GUID of a model of joystick, its name and the bindings
+//! of its controls, like
+//! `030000005e0400008e02000014010000,X360 Controller,a:b0,b:b1,leftx:a0,platform:Linux,`.
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use gamepad::{AxisRange, Binding, GamepadAxis, GamepadButton, Mapping, Source, Target};
+use {Axis, Button, Guid, Hat, HatPos, Joystick};
+
+/// The name SDL gives the platform this was compiled for
+#[cfg(target_os = "linux")]
+const PLATFORM: &'static str = "Linux";
+#[cfg(target_os = "windows")]
+const PLATFORM: &'static str = "Windows";
+#[cfg(target_os = "macos")]
+const PLATFORM: &'static str = "Mac OS X";
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+const PLATFORM: &'static str = "";
+
+/// An error from parsing a malformed mapping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseMappingError {
+	reason: String
+}
+
+impl ParseMappingError {
+	fn new<S>(reason: S) -> ParseMappingError where S: Into<String> {
+		ParseMappingError { reason: reason.into() }
+	}
+}
+
+impl fmt::Display for ParseMappingError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid mapping: {}", self.reason)
+	}
+}
+
+impl Error for ParseMappingError {}
+
+/// Parse a single mapping line into the GUID it is for and the mapping.
+///
+/// Keys that aren't standard gamepad controls, like `platform` or `misc1`,
+/// are ignored.
+///
+/// ``` rust
+/// use reminisce::gamepad::{AxisRange, Binding, GamepadAxis, GamepadButton, Source, Target};
+/// use reminisce::mapping;
+///
+/// let (guid, mapping) = mapping::parse(
+///     "030000005e0400008e02000014010000,X360 Controller,a:b0,dpup:h0.1,lefttrigger:a2~,platform:Linux,"
+/// ).unwrap();
+/// assert_eq!(guid.to_string(), "030000005e0400008e02000014010000");
+/// assert_eq!(mapping.name, "X360 Controller");
+/// assert_eq!(mapping.bindings[0], Binding {
+///     source: Source::Button(0),
+///     target: Target::Button(GamepadButton::South)
+/// });
+/// assert_eq!(mapping.bindings[2].source, Source::Axis(2, AxisRange::Full, true));
+/// ```
+pub fn parse(line: &str) -> Result<(Guid, Mapping), ParseMappingError> {
+	let mut fields = line.trim().split(',');
+	let guid = match fields.next().map(|guid| guid.parse::<Guid>()) {
+		Some(Ok(guid)) => guid,
+		_ => return Err(ParseMappingError::new("the GUID must be 32 hex digits"))
+	};
+	let name = match fields.next() {
+		Some(name) => name.to_owned(),
+		None => return Err(ParseMappingError::new("missing name"))
+	};
+	let mut bindings = Vec::new();
+	for field in fields {
+		if field.is_empty() {
+			continue
+		}
+		let mut parts = field.splitn(2, ':');
+		let (key, value) = match (parts.next(), parts.next()) {
+			(Some(key), Some(value)) => (key, value),
+			_ => return Err(ParseMappingError::new(format!("`{}` isn't a key and value", field)))
+		};
+		let target = match parse_target(key) {
+			Some(target) => target,
+			None => continue
+		};
+		if value.is_empty() {
+			continue
+		}
+		let source = match parse_source(value) {
+			Some(source) => source,
+			None => return Err(ParseMappingError::new(format!("`{}` isn't a button, axis or hat", value)))
+		};
+		bindings.push(Binding { source: source, target: target });
+	}
+	Ok((guid, Mapping {
+		name: name,
+		bindings: bindings
+	}))
+}
+
+/// Parse a gamepad control like `a`, `leftx` or `+lefty`
+fn parse_target(key: &str) -> Option<Target> {
+	let (range, key) = split_sign(key);
+	let button = match key {
+		"a" => GamepadButton::South,
+		"b" => GamepadButton::East,
+		"x" => GamepadButton::West,
+		"y" => GamepadButton::North,
+		"back" => GamepadButton::Back,
+		"guide" => GamepadButton::Guide,
+		"start" => GamepadButton::Start,
+		"leftstick" => GamepadButton::LeftStick,
+		"rightstick" => GamepadButton::RightStick,
+		"leftshoulder" => GamepadButton::LeftShoulder,
+		"rightshoulder" => GamepadButton::RightShoulder,
+		"dpup" => GamepadButton::DPadUp,
+		"dpdown" => GamepadButton::DPadDown,
+		"dpleft" => GamepadButton::DPadLeft,
+		"dpright" => GamepadButton::DPadRight,
+		_ => {
+			let axis = match key {
+				"leftx" => GamepadAxis::LeftX,
+				"lefty" => GamepadAxis::LeftY,
+				"rightx" => GamepadAxis::RightX,
+				"righty" => GamepadAxis::RightY,
+				"lefttrigger" => GamepadAxis::LeftTrigger,
+				"righttrigger" => GamepadAxis::RightTrigger,
+				_ => return None
+			};
+			return Some(Target::Axis(axis, range))
+		}
+	};
+	Some(Target::Button(button))
+}
+
+/// Parse a joystick control like `b3`, `-a2`, `a1~` or `h0.4`
+fn parse_source(value: &str) -> Option<Source> {
+	let (range, value) = split_sign(value);
+	let (inverted, value) = if value.ends_with('~') {
+		(true, &value[..value.len() - 1])
+	} else {
+		(false, value)
+	};
+	if value.len() < 2 {
+		return None
+	}
+	let (kind, rest) = value.split_at(1);
+	match kind {
+		"b" => rest.parse::<Button>().ok().map(Source::Button),
+		"a" => rest.parse::<Axis>().ok().map(|axis| Source::Axis(axis, range, inverted)),
+		"h" => {
+			let mut parts = rest.splitn(2, '.');
+			let hat = match parts.next().map(|hat| hat.parse::<Hat>()) {
+				Some(Ok(hat)) => hat,
+				_ => return None
+			};
+			let pos = match parts.next().map(|bits| bits.parse::<u8>()) {
+				Some(Ok(bits)) if bits != 0 && HatPos::from_bits(bits).bits() == bits => HatPos::from_bits(bits),
+				_ => return None
+			};
+			Some(Source::Hat(hat, pos))
+		},
+		_ => None
+	}
+}
+
+/// Split a leading `+` or `-` off of a key or value
+fn split_sign(text: &str) -> (AxisRange, &str) {
+	if text.starts_with('+') {
+		(AxisRange::Positive, &text[1..])
+	} else if text.starts_with('-') {
+		(AxisRange::Negative, &text[1..])
+	} else {
+		(AxisRange::Full, text)
+	}
+}
+
+/// Get the platform a mapping line is restricted to, if any
+fn platform(line: &str) -> Option<&str> {
+	line.split(',')
+		.find(|field| field.starts_with("platform:"))
+		.map(|field| &field["platform:".len()..])
+}
+
+/// A set of mappings, keyed by the GUIDs of the joysticks they are for.
+///
+/// ``` rust
+/// use reminisce::gamepad::Source;
+/// use reminisce::mapping::MappingDb;
+///
+/// let mut db = MappingDb::new();
+/// db.add_str("\
+///     # Mappings for other platforms are skipped\n\
+///     030000005e0400008e02000014010000,X360 Controller,a:b0,\n\
+///     030000005e0400008e02000014010000,X360 Controller,a:b1,platform:Haiku,\n");
+/// let guid = "030000005e0400008e02000014010000".parse().unwrap();
+/// assert_eq!(db.get(&guid).unwrap().bindings[0].source, Source::Button(0));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MappingDb {
+	mappings: HashMap<Guid, Mapping>
+}
+
+impl MappingDb {
+	/// Create a new database without any mappings
+	pub fn new() -> MappingDb {
+		MappingDb {
+			mappings: HashMap::new()
+		}
+	}
+	/// Add a mapping for the GUID given, replacing any mapping it already has
+	pub fn insert(&mut self, guid: Guid, mapping: Mapping) {
+		self.mappings.insert(guid, mapping);
+	}
+	/// Add each mapping line in `text` for this platform, returning how many
+	/// were added.
+	///
+	/// Blank lines, comments starting with `#` and lines that can't be
+	/// parsed are skipped.
+	pub fn add_str(&mut self, text: &str) -> usize {
+		let mut added = 0;
+		for line in text.lines().map(|line| line.trim()) {
+			if line.is_empty() || line.starts_with('#') {
+				continue
+			}
+			if platform(line).map_or(false, |platform| platform != PLATFORM) {
+				continue
+			}
+			if let Ok((guid, mapping)) = parse(line) {
+				self.insert(guid, mapping);
+				added += 1;
+			}
+		}
+		added
+	}
+	/// Add the mappings in a file like `gamecontrollerdb.txt`, returning how
+	/// many were added
+	pub fn add_file<P>(&mut self, path: P) -> io::Result<usize> where P: AsRef<Path> {
+		let mut text = String::new();
+		try!(try!(File::open(path)).read_to_string(&mut text));
+		Ok(self.add_str(&text))
+	}
+	/// Add the mappings in the `SDL_GAMECONTROLLERCONFIG` environment
+	/// variable, returning how many were added
+	pub fn add_env(&mut self) -> usize {
+		match env::var("SDL_GAMECONTROLLERCONFIG") {
+			Ok(text) => self.add_str(&text),
+			Err(_) => 0
+		}
+	}
+	/// Get the mapping for the GUID given
+	pub fn get(&self, guid: &Guid) -> Option<&Mapping> {
+		self.mappings.get(guid)
+	}
+	/// Get the mapping for a joystick by its GUID.
+	///
+	/// Like SDL, this falls back to ignoring the CRC and then the version in
+	/// the GUID if there isn't an exact match.
+	pub fn lookup<J>(&self, joystick: &J) -> Option<&Mapping> where J: Joystick {
+		let mut guid = match joystick.device_id() {
+			Some(id) => id.guid,
+			None => return None
+		};
+		if let Some(mapping) = self.get(&guid) {
+			return Some(mapping)
+		}
+		guid.0[2] = 0;
+		guid.0[3] = 0;
+		if let Some(mapping) = self.get(&guid) {
+			return Some(mapping)
+		}
+		guid.0[12] = 0;
+		guid.0[13] = 0;
+		self.get(&guid)
+	}
+	/// Get the number of mappings
+	pub fn len(&self) -> usize {
+		self.mappings.len()
+	}
+	/// Check if there aren't any mappings
+	pub fn is_empty(&self) -> bool {
+		self.mappings.is_empty()
+	}
+}
@@ -0,0 +1,434 @@
+//! Motion inputs for fighting games, like quarter circles and charges,
+//! matched against the history of a stick or hat.
+//!
+//! Directions use numpad notation for a character facing right, so `6` is
+//! forward, `4` is back, `2` is down and `5` is neutral. Patterns are written
+//! the same way followed by the buttons that finish them, like `236P` for a
+//! quarter circle forward and punch or `[4]6P` for a back charge.
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use {Axis, Backend, Button, Event, Hat, HatPos, JoystickIndex, TimedEvent, Timestamp};
+
+/// A direction of a stick or hat in numpad notation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+	DownBack = 1,
+	Down = 2,
+	DownForward = 3,
+	Back = 4,
+	Neutral = 5,
+	Forward = 6,
+	UpBack = 7,
+	Up = 8,
+	UpForward = 9
+}
+
+impl Direction {
+	/// Get the direction with the numpad digit given
+	pub fn from_numpad(digit: u8) -> Option<Direction> {
+		Some(match digit {
+			1 => Direction::DownBack,
+			2 => Direction::Down,
+			3 => Direction::DownForward,
+			4 => Direction::Back,
+			5 => Direction::Neutral,
+			6 => Direction::Forward,
+			7 => Direction::UpBack,
+			8 => Direction::Up,
+			9 => Direction::UpForward,
+			_ => return None
+		})
+	}
+	/// Get the direction a stick pointing along `x` and `y` is in, where each
+	/// is `-1`, `0` or `1` and negative `y` is up
+	pub fn from_xy(x: i8, y: i8) -> Direction {
+		let column = x.signum() + 1;
+		let row = 1 - y.signum();
+		Direction::from_numpad((row * 3 + column + 1) as u8).unwrap()
+	}
+	/// Get the direction of a stick from its axes, ignoring movement below
+	/// the threshold given
+	pub fn from_axes(x: f32, y: f32, threshold: f32) -> Direction {
+		let sign = |value: f32| if value < -threshold { -1 } else if value > threshold { 1 } else { 0 };
+		Direction::from_xy(sign(x), sign(y))
+	}
+	/// Get the direction of a hat
+	pub fn from_hat(pos: HatPos) -> Direction {
+		Direction::from_xy(pos.x(), pos.y())
+	}
+	/// Get the horizontal part of this direction, which is `-1` for back
+	pub fn x(self) -> i8 {
+		(self as i8 - 1) % 3 - 1
+	}
+	/// Get the vertical part of this direction, which is `-1` for up
+	pub fn y(self) -> i8 {
+		1 - (self as i8 - 1) / 3
+	}
+	/// Get this direction for a character facing the other way
+	pub fn mirrored(self) -> Direction {
+		Direction::from_xy(-self.x(), self.y())
+	}
+	/// Check if this direction counts towards a charge in `charge`, which it
+	/// does if it shares its horizontal or vertical part
+	fn charges(self, charge: Direction) -> bool {
+		(charge.x() != 0 && self.x() == charge.x()) || (charge.y() != 0 && self.y() == charge.y())
+	}
+}
+
+/// A step of a motion.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+	/// Move to the direction given
+	Press(Direction),
+	/// Hold the direction given, or a diagonal next to it, for the charge
+	/// time
+	Charge(Direction)
+}
+
+/// A motion followed by the buttons that finish it.
+///
+/// ``` rust
+/// use reminisce::motion::{Direction, Pattern, Step};
+/// let pattern: Pattern = "[4]6P".parse().unwrap();
+/// assert_eq!(pattern.steps, vec![Step::Charge(Direction::Back), Step::Press(Direction::Forward)]);
+/// assert_eq!(pattern.buttons, vec!['P']);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+	pub steps: Vec<Step>,
+	/// The names of the buttons, which must all be pressed together
+	pub buttons: Vec<char>
+}
+
+impl Pattern {
+	/// Get this pattern for a character facing the other way
+	pub fn mirrored(&self) -> Pattern {
+		Pattern {
+			steps: self.steps.iter().map(|&step| match step {
+				Step::Press(dir) => Step::Press(dir.mirrored()),
+				Step::Charge(dir) => Step::Charge(dir.mirrored())
+			}).collect(),
+			buttons: self.buttons.clone()
+		}
+	}
+}
+
+/// An error from parsing a malformed pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePatternError {
+	reason: String
+}
+
+impl ParsePatternError {
+	fn new(reason: &str) -> ParsePatternError {
+		ParsePatternError { reason: reason.to_owned() }
+	}
+}
+
+impl fmt::Display for ParsePatternError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid pattern: {}", self.reason)
+	}
+}
+
+impl Error for ParsePatternError {}
+
+impl FromStr for Pattern {
+	type Err = ParsePatternError;
+	fn from_str(text: &str) -> Result<Pattern, ParsePatternError> {
+		let mut steps = Vec::new();
+		let mut buttons = Vec::new();
+		let mut chars = text.chars();
+		while let Some(c) = chars.next() {
+			let direction = |c: Option<char>| c.and_then(|c| c.to_digit(10))
+				.and_then(|digit| Direction::from_numpad(digit as u8));
+			if c == '[' {
+				let charge = match direction(chars.next()) {
+					Some(charge) => charge,
+					None => return Err(ParsePatternError::new("a charge must be a direction"))
+				};
+				if chars.next() != Some(']') {
+					return Err(ParsePatternError::new("a charge must end with `]`"))
+				}
+				steps.push(Step::Charge(charge));
+			} else if let Some(dir) = direction(Some(c)) {
+				if !buttons.is_empty() {
+					return Err(ParsePatternError::new("the buttons must come after the motion"))
+				}
+				steps.push(Step::Press(dir));
+			} else if c.is_alphabetic() {
+				buttons.push(c);
+			} else if !c.is_whitespace() {
+				return Err(ParsePatternError::new("expected a direction, charge or button"))
+			}
+		}
+		if buttons.is_empty() {
+			return Err(ParsePatternError::new("there must be a button"))
+		}
+		Ok(Pattern {
+			steps: steps,
+			buttons: buttons
+		})
+	}
+}
+
+/// The times in milliseconds that motions are matched within.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timing {
+	/// The longest time from the first step of a motion to its buttons
+	pub motion: Timestamp,
+	/// The longest time between the last step of a motion and its buttons,
+	/// and between the buttons of a pattern
+	pub buffer: Timestamp,
+	/// How long a charge must be held for
+	pub charge: Timestamp
+}
+
+impl Default for Timing {
+	fn default() -> Timing {
+		Timing {
+			motion: 500,
+			buffer: 100,
+			charge: 750
+		}
+	}
+}
+
+/// A pattern that was matched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Motion {
+	pub joystick: JoystickIndex,
+	/// The name the pattern was added with
+	pub name: String,
+	pub time: Timestamp
+}
+
+/// The recent input of a joystick
+#[derive(Default)]
+struct History {
+	stick: (f32, f32),
+	hat: Option<HatPos>,
+	/// Each direction moved to and when
+	directions: Vec<(Direction, Timestamp)>,
+	/// When each button was last pressed
+	presses: HashMap<Button, Timestamp>
+}
+
+/// Matches patterns against the directions of a stick or hat and the
+/// buttons of each joystick.
+///
+/// Directions come from hat `0` while it is pushed and from axes `0` and `1`
+/// otherwise, unless set with `set_stick` and `set_hat`. Patterns are tried
+/// in the order they were added and only the first that matches is
+/// reported, so add longer motions like `623P` before ones they contain like
+/// `236P`.
+///
+/// ``` rust
+/// use reminisce::{Event, TimedEvent};
+/// use reminisce::motion::Matcher;
+///
+/// let mut matcher = Matcher::new();
+/// matcher.bind_button('P', 2);
+/// matcher.add_pattern("Hadouken", "236P".parse().unwrap());
+/// for &(event, time) in &[
+///     (Event::AxisMoved(0, 1, 1.0), 0),
+///     (Event::AxisMoved(0, 0, 1.0), 50),
+///     (Event::AxisMoved(0, 1, 0.0), 100),
+///     (Event::ButtonPressed(0, 2), 130)
+/// ] {
+///     matcher.process(TimedEvent { event: event, time: time });
+/// }
+/// assert_eq!(matcher.poll().unwrap().name, "Hadouken");
+/// ```
+pub struct Matcher {
+	patterns: Vec<(String, Pattern)>,
+	buttons: HashMap<char, Button>,
+	timing: Timing,
+	threshold: f32,
+	stick: (Axis, Axis),
+	hat: Hat,
+	joysticks: HashMap<JoystickIndex, History>,
+	pending: VecDeque<Motion>
+}
+
+impl Default for Matcher {
+	fn default() -> Matcher {
+		Matcher::new()
+	}
+}
+
+impl Matcher {
+	/// Create a new matcher without any patterns
+	pub fn new() -> Matcher {
+		Matcher {
+			patterns: Vec::new(),
+			buttons: HashMap::new(),
+			timing: Timing::default(),
+			threshold: 0.5,
+			stick: (0, 1),
+			hat: 0,
+			joysticks: HashMap::new(),
+			pending: VecDeque::new()
+		}
+	}
+	/// Set the times motions are matched within
+	pub fn set_timing(&mut self, timing: Timing) {
+		self.timing = timing;
+	}
+	/// Set how far the stick must be pushed to count as a direction
+	pub fn set_threshold(&mut self, threshold: f32) {
+		self.threshold = threshold;
+	}
+	/// Read directions from the `x` and `y` axes given
+	pub fn set_stick(&mut self, x: Axis, y: Axis) {
+		self.stick = (x, y);
+	}
+	/// Read directions from the hat given
+	pub fn set_hat(&mut self, hat: Hat) {
+		self.hat = hat;
+	}
+	/// Use `button` for the button named `name` in patterns
+	pub fn bind_button(&mut self, name: char, button: Button) {
+		self.buttons.insert(name, button);
+	}
+	/// Add a pattern to match, reported with the name given
+	pub fn add_pattern(&mut self, name: &str, pattern: Pattern) {
+		self.patterns.push((name.to_owned(), pattern));
+	}
+	/// Get the direction a joystick is being held in
+	pub fn direction(&self, joystick: JoystickIndex) -> Direction {
+		self.joysticks.get(&joystick)
+			.and_then(|history| history.directions.last())
+			.map_or(Direction::Neutral, |&(dir, _)| dir)
+	}
+	/// Match patterns with a timed event
+	pub fn process(&mut self, event: TimedEvent) {
+		let time = event.time;
+		let (index, moved) = match event.event {
+			Event::Disconnected(index) => {
+				self.joysticks.remove(&index);
+				return
+			},
+			Event::AxisMoved(index, axis, value) => {
+				let history = self.joysticks.entry(index).or_insert_with(History::default);
+				if axis == self.stick.0 {
+					history.stick.0 = value;
+				} else if axis == self.stick.1 {
+					history.stick.1 = value;
+				} else {
+					return
+				}
+				(index, true)
+			},
+			Event::HatMoved(index, hat, pos) if hat == self.hat => {
+				self.joysticks.entry(index).or_insert_with(History::default).hat = Some(pos);
+				(index, true)
+			},
+			Event::ButtonPressed(index, button) => {
+				self.joysticks.entry(index).or_insert_with(History::default).presses.insert(button, time);
+				(index, false)
+			},
+			_ => return
+		};
+		let keep = self.timing.motion + self.timing.charge;
+		let threshold = self.threshold;
+		let history = self.joysticks.get_mut(&index).unwrap();
+		if moved {
+			let dir = match history.hat {
+				Some(pos) if pos != HatPos::Centered => Direction::from_hat(pos),
+				_ => Direction::from_axes(history.stick.0, history.stick.1, threshold)
+			};
+			if history.directions.last().map(|&(last, _)| last) != Some(dir) {
+				history.directions.push((dir, time));
+			}
+			// Forget directions too old to be part of any motion, keeping the
+			// one held since then
+			let old = history.directions.iter()
+				.take_while(|&&(_, since)| time.wrapping_sub(since) > keep)
+				.count();
+			if old > 1 {
+				history.directions.drain(..old - 1);
+			}
+			return
+		}
+		let timing = self.timing;
+		let buttons = &self.buttons;
+		let matched = self.patterns.iter().find(|&&(_, ref pattern)| {
+			let pressed = pattern.buttons.iter().all(|name| buttons.get(name)
+				.and_then(|button| history.presses.get(button))
+				.map_or(false, |&pressed| time.wrapping_sub(pressed) <= timing.buffer));
+			pressed && matches(&pattern.steps, &history.directions, time, timing)
+		});
+		if let Some(&(ref name, _)) = matched {
+			// Stop the buttons finishing another motion straight away
+			history.presses.clear();
+			self.pending.push_back(Motion {
+				joystick: index,
+				name: name.clone(),
+				time: time
+			});
+		}
+	}
+	/// Process every event that is ready from the backend given
+	pub fn update<B>(&mut self, backend: &mut B) where B: Backend {
+		while let Some(event) = backend.poll_timed() {
+			self.process(event);
+		}
+	}
+	/// Get the next motion that was matched
+	pub fn poll(&mut self) -> Option<Motion> {
+		self.pending.pop_front()
+	}
+	/// Iterate through the motions that were matched
+	pub fn iter(&mut self) -> Motions {
+		Motions { matcher: self }
+	}
+}
+
+/// An iterator over the motions a `Matcher` has matched.
+pub struct Motions<'a> {
+	matcher: &'a mut Matcher
+}
+
+impl<'a> Iterator for Motions<'a> {
+	type Item = Motion;
+	fn next(&mut self) -> Option<Motion> {
+		self.matcher.poll()
+	}
+}
+
+/// Check if the steps of a motion were made in the directions given, with
+/// the buttons being pressed at `now`
+fn matches(steps: &[Step], directions: &[(Direction, Timestamp)], now: Timestamp, timing: Timing) -> bool {
+	let end_of = |i: usize| directions.get(i + 1).map_or(now, |&(_, since)| since);
+	let mut next = directions.len();
+	let mut first_end = now;
+	for (n, &step) in steps.iter().rev().enumerate() {
+		let found = (0..next).rev().find(|&i| match step {
+			Step::Press(dir) => directions[i].0 == dir,
+			Step::Charge(dir) => directions[i].0.charges(dir)
+		});
+		let i = match found {
+			Some(i) => i,
+			None => return false
+		};
+		// The last step must be held until just before the buttons
+		if n == 0 && now.wrapping_sub(end_of(i)) > timing.buffer {
+			return false
+		}
+		if let Step::Charge(dir) = step {
+			let start = (0..i + 1).rev()
+				.take_while(|&j| directions[j].0.charges(dir))
+				.last()
+				.map_or(now, |j| directions[j].1);
+			if end_of(i).wrapping_sub(start) < timing.charge {
+				return false
+			}
+		}
+		first_end = end_of(i);
+		next = i;
+	}
+	now.wrapping_sub(first_end) <= timing.motion
+}
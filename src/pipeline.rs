@@ -0,0 +1,464 @@
+//! A chain of filters that rewrite, suppress or inject events on their way
+//! out of a `Backend`.
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use curve::Curves;
+use deadzone::DeadZones;
+use {Axis, Backend, BackendError, Button, Event, JoystickIndex, TimedEvent, Timestamp};
+
+/// A stage of a `Pipeline`, which sees each event and decides what to emit
+/// in its place.
+///
+/// Closures taking an event and the output can be used as filters.
+///
+/// ``` rust
+/// use reminisce::{Event, TimedEvent};
+/// use reminisce::pipeline::{Filter, RemapButton};
+///
+/// let mut out = Vec::new();
+/// RemapButton::new(None, 0, 3).filter(TimedEvent { event: Event::ButtonPressed(0, 0), time: 0 }, &mut out);
+/// assert_eq!(out[0].event, Event::ButtonPressed(0, 3));
+/// ```
+pub trait Filter {
+	/// Process an event, pushing the events to emit instead onto `out`.
+	///
+	/// Pushing nothing suppresses the event, and pushing more than one
+	/// injects events.
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>);
+	/// Push any events that were held back until the time given, which is on
+	/// the backend's clock.
+	fn flush(&mut self, _now: Timestamp, _out: &mut Vec<TimedEvent>) {}
+	/// Get the time this filter next needs `flush` to be called at, if it is
+	/// holding any events back
+	fn next_flush(&self) -> Option<Timestamp> {
+		None
+	}
+}
+
+impl<F> Filter for F where F: FnMut(TimedEvent, &mut Vec<TimedEvent>) {
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		self(event, out)
+	}
+}
+
+/// A backend whose events pass through a chain of filters, in the order they
+/// were added.
+///
+/// ``` rust
+/// use reminisce::{Backend, Event, Native};
+/// use reminisce::deadzone::{DeadZone, DeadZones};
+/// use reminisce::pipeline::{DropDevice, InvertAxis, Pipeline};
+///
+/// let mut zones = DeadZones::new();
+/// zones.set_pair(None, 0, 1, DeadZone::scaled_radial(0.15));
+/// if let Ok(backend) = Native::try_new() {
+///     let mut pipeline = Pipeline::new(backend)
+///         .with(DropDevice(3))
+///         .with(InvertAxis::new(None, 1))
+///         .with(zones)
+///         .with(|event, out: &mut Vec<_>| out.push(event));
+///     for event in pipeline.iter().take(30) {
+///         println!("{:?}", event)
+///     }
+/// }
+/// ```
+///
+/// Events a filter holds back are flushed through the filters after it once
+/// they are due, and waiting wakes up early for them.
+///
+/// ``` rust
+/// use std::time::{Duration, Instant};
+/// use reminisce::{Backend, Event, TimedEvent};
+/// use reminisce::pipeline::{Debounce, Pipeline, RemapButton};
+/// # use reminisce::testing::Script;
+///
+/// # let mut backend = Script::new(Vec::new());
+/// backend.push(Event::ButtonPressed(0, 0), 1000);
+/// // Too soon after the press, so this is held back until 1030
+/// backend.push(Event::ButtonReleased(0, 0), 1010);
+/// let mut pipeline = Pipeline::new(backend)
+///     .with(Debounce::new(30))
+///     .with(RemapButton::new(None, 0, 3))
+///     .with(|event: TimedEvent, out: &mut Vec<TimedEvent>| {
+///         out.push(event);
+///         // Inject a full tilt of axis 9 after every press
+///         if let Event::ButtonPressed(index, _) = event.event {
+///             out.push(TimedEvent { event: Event::AxisMoved(index, 9, 1.0), time: event.time });
+///         }
+///     });
+/// assert_eq!(pipeline.poll(), Some(Event::ButtonPressed(0, 3)));
+/// assert_eq!(pipeline.poll(), Some(Event::AxisMoved(0, 9, 1.0)));
+/// assert_eq!(pipeline.poll(), None);
+///
+/// let start = Instant::now();
+/// let release = pipeline.wait_timed(Some(Duration::from_secs(5)));
+/// assert_eq!(release, Some(TimedEvent { event: Event::ButtonReleased(0, 3), time: 1030 }));
+/// assert!(start.elapsed() < Duration::from_secs(1));
+/// ```
+pub struct Pipeline<B> where B: Backend {
+	backend: B,
+	filters: Vec<Box<dyn Filter>>,
+	pending: VecDeque<TimedEvent>,
+	/// The time of the last event from the backend and when it arrived, for
+	/// estimating the backend's clock
+	last_seen: Option<(Timestamp, Instant)>
+}
+
+impl<B> Pipeline<B> where B: Backend {
+	/// Create a new pipeline over the backend given, without any filters
+	pub fn new(backend: B) -> Pipeline<B> {
+		Pipeline {
+			backend: backend,
+			filters: Vec::new(),
+			pending: VecDeque::new(),
+			last_seen: None
+		}
+	}
+	/// Add a filter to the end of the pipeline
+	pub fn with<F>(mut self, filter: F) -> Pipeline<B> where F: Filter + 'static {
+		self.push(filter);
+		self
+	}
+	/// Add a filter to the end of the pipeline
+	pub fn push<F>(&mut self, filter: F) where F: Filter + 'static {
+		self.filters.push(Box::new(filter));
+	}
+	/// Get a reference to the backend
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+	/// Get a mutable reference to the backend
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+	/// Remove the filters and return the backend
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+	/// Run an event from the backend through every filter and queue the
+	/// results
+	fn run(&mut self, event: TimedEvent) {
+		self.last_seen = Some((event.time, Instant::now()));
+		self.run_from(0, vec![event]);
+	}
+	/// Run events through the filters from the one at `first` onwards and
+	/// queue the results
+	fn run_from(&mut self, first: usize, mut events: Vec<TimedEvent>) {
+		for filter in &mut self.filters[first..] {
+			let mut out = Vec::with_capacity(events.len());
+			for event in events {
+				filter.filter(event, &mut out);
+			}
+			events = out;
+		}
+		self.pending.extend(events);
+	}
+	/// Estimate the time on the backend's clock from the last event
+	fn now(&self) -> Option<Timestamp> {
+		self.last_seen.map(|(time, seen)| {
+			let elapsed = seen.elapsed();
+			time.wrapping_add(elapsed.as_secs() as u32 * 1000 + elapsed.subsec_nanos() / 1_000_000)
+		})
+	}
+	/// Queue the events every filter held back until now
+	fn flush(&mut self) {
+		let now = match self.now() {
+			Some(now) => now,
+			None => return
+		};
+		for first in 0..self.filters.len() {
+			let mut out = Vec::new();
+			self.filters[first].flush(now, &mut out);
+			if !out.is_empty() {
+				self.run_from(first + 1, out);
+			}
+		}
+	}
+	/// Get how long until a filter next needs flushing
+	fn until_flush(&self) -> Option<Duration> {
+		let now = match self.now() {
+			Some(now) => now,
+			None => return None
+		};
+		self.filters.iter()
+			.filter_map(|filter| filter.next_flush())
+			.map(|time| {
+				let left = time.wrapping_sub(now);
+				// Times that have already passed wrap around to huge numbers
+				Duration::from_millis(if left > Timestamp::max_value() / 2 { 0 } else { left as u64 })
+			})
+			.min()
+	}
+}
+
+impl<B> Backend for Pipeline<B> where B: Backend {
+	type Joystick = B::Joystick;
+	fn try_new() -> Result<Pipeline<B>, BackendError> {
+		B::try_new().map(Pipeline::new)
+	}
+	fn num_joysticks(&self) -> usize {
+		self.backend.num_joysticks()
+	}
+	fn joysticks(&self) -> &[B::Joystick] {
+		self.backend.joysticks()
+	}
+	fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Ok(Some(event))
+			}
+			self.flush();
+			if !self.pending.is_empty() {
+				continue
+			}
+			match try!(self.backend.try_poll_timed()) {
+				Some(event) => self.run(event),
+				None => return Ok(None)
+			}
+		}
+	}
+	/// This waits on the backend, so it sleeps however the backend does,
+	/// waking early when a filter has events to release
+	fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		loop {
+			if let Some(event) = self.pending.pop_front() {
+				return Ok(Some(event))
+			}
+			self.flush();
+			if !self.pending.is_empty() {
+				continue
+			}
+			let left = match deadline {
+				Some(deadline) => {
+					let now = Instant::now();
+					if now >= deadline {
+						return Ok(None)
+					}
+					Some(deadline - now)
+				},
+				None => None
+			};
+			let flush = self.until_flush();
+			let wait = match (left, flush) {
+				(Some(left), Some(flush)) => Some(cmp::min(left, flush)),
+				(left, None) => left,
+				(None, flush) => flush
+			};
+			match try!(self.backend.try_wait_timed(wait)) {
+				Some(event) => self.run(event),
+				// Only give up if the wait wasn't cut short for a flush
+				None if flush.is_none() || wait == left => return Ok(None),
+				None => ()
+			}
+		}
+	}
+}
+
+#[cfg(unix)]
+impl<B> AsRawFd for Pipeline<B> where B: Backend + AsRawFd {
+	fn as_raw_fd(&self) -> RawFd {
+		self.backend.as_raw_fd()
+	}
+}
+
+/// Renumber a button of one joystick, or of every joystick if none is given.
+pub struct RemapButton {
+	index: Option<JoystickIndex>,
+	from: Button,
+	to: Button
+}
+
+impl RemapButton {
+	/// Report the button `from` as the button `to`
+	pub fn new(index: Option<JoystickIndex>, from: Button, to: Button) -> RemapButton {
+		RemapButton {
+			index: index,
+			from: from,
+			to: to
+		}
+	}
+	fn matches(&self, index: JoystickIndex, button: Button) -> bool {
+		self.index.map_or(true, |i| i == index) && button == self.from
+	}
+}
+
+impl Filter for RemapButton {
+	fn filter(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		event.event = match event.event {
+			Event::ButtonPressed(index, button) if self.matches(index, button) =>
+				Event::ButtonPressed(index, self.to),
+			Event::ButtonReleased(index, button) if self.matches(index, button) =>
+				Event::ButtonReleased(index, self.to),
+			other => other
+		};
+		out.push(event);
+	}
+}
+
+/// Invert an axis of one joystick, or of every joystick if none is given.
+pub struct InvertAxis {
+	index: Option<JoystickIndex>,
+	axis: Axis
+}
+
+impl InvertAxis {
+	pub fn new(index: Option<JoystickIndex>, axis: Axis) -> InvertAxis {
+		InvertAxis {
+			index: index,
+			axis: axis
+		}
+	}
+}
+
+impl Filter for InvertAxis {
+	fn filter(&mut self, mut event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		if let Event::AxisMoved(index, axis, value) = event.event {
+			if self.index.map_or(true, |i| i == index) && axis == self.axis {
+				event.event = Event::AxisMoved(index, axis, -value);
+			}
+		}
+		out.push(event);
+	}
+}
+
+/// Drop every event from the joystick with the index given.
+pub struct DropDevice(pub JoystickIndex);
+
+impl Filter for DropDevice {
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		let index = match event.event {
+			Event::Connected(index) | Event::Disconnected(index) |
+			Event::ButtonPressed(index, _) | Event::ButtonReleased(index, _) |
+			Event::AxisMoved(index, _, _) | Event::HatMoved(index, _, _) => index
+		};
+		if index != self.0 {
+			out.push(event);
+		}
+	}
+}
+
+/// Hold back button presses and releases that happen too soon after the
+/// last one of the same button, which filters out switches that bounce.
+///
+/// When the interval is up, the button is reported in whatever state it
+/// ended up in, so a real tap shorter than the interval still comes through,
+/// just late. Filters that hold events back like this are flushed by the
+/// `Pipeline` as its backend's clock passes.
+///
+/// ``` rust
+/// use reminisce::{Event, TimedEvent};
+/// use reminisce::pipeline::{Debounce, Filter};
+///
+/// let mut debounce = Debounce::new(30);
+/// let mut out = Vec::new();
+/// for &(event, time) in &[
+///     (Event::ButtonPressed(0, 1), 1000),
+///     (Event::ButtonReleased(0, 1), 1010),
+///     (Event::ButtonPressed(0, 1), 1015),
+///     (Event::ButtonReleased(0, 1), 1020),
+///     (Event::ButtonPressed(0, 1), 2000)
+/// ] {
+///     debounce.filter(TimedEvent { event: event, time: time }, &mut out);
+/// }
+/// let out: Vec<(Event, u32)> = out.into_iter().map(|event| (event.event, event.time)).collect();
+/// assert_eq!(out, vec![
+///     (Event::ButtonPressed(0, 1), 1000),
+///     (Event::ButtonReleased(0, 1), 1030),
+///     (Event::ButtonPressed(0, 1), 2000)
+/// ]);
+/// ```
+pub struct Debounce {
+	interval: Timestamp,
+	buttons: HashMap<(JoystickIndex, Button), Bouncing>
+}
+
+/// The state of a button being debounced
+struct Bouncing {
+	/// Whether the button was last reported as pressed
+	reported: bool,
+	/// Whether the button is really pressed
+	pressed: bool,
+	/// The time the button was last reported to change
+	since: Timestamp
+}
+
+impl Debounce {
+	/// Hold back button changes less than `interval` milliseconds apart
+	pub fn new(interval: Timestamp) -> Debounce {
+		Debounce {
+			interval: interval,
+			buttons: HashMap::new()
+		}
+	}
+}
+
+impl Filter for Debounce {
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		self.flush(event.time, out);
+		let (key, pressed) = match event.event {
+			Event::ButtonPressed(index, button) => ((index, button), true),
+			Event::ButtonReleased(index, button) => ((index, button), false),
+			Event::Disconnected(index) => {
+				self.buttons.retain(|&(i, _), _| i != index);
+				out.push(event);
+				return
+			},
+			_ => {
+				out.push(event);
+				return
+			}
+		};
+		let interval = self.interval;
+		let button = self.buttons.entry(key).or_insert(Bouncing { reported: !pressed, pressed: !pressed, since: event.time.wrapping_sub(interval) });
+		button.pressed = pressed;
+		if button.reported != pressed && event.time.wrapping_sub(button.since) >= interval {
+			button.reported = pressed;
+			button.since = event.time;
+			out.push(event);
+		}
+	}
+	fn flush(&mut self, now: Timestamp, out: &mut Vec<TimedEvent>) {
+		let interval = self.interval;
+		let mut due = Vec::new();
+		for (&(index, button), state) in &mut self.buttons {
+			let time = state.since.wrapping_add(interval);
+			let waited = now.wrapping_sub(state.since);
+			if state.reported != state.pressed && waited >= interval && waited <= Timestamp::max_value() / 2 {
+				state.reported = state.pressed;
+				state.since = time;
+				let event = if state.pressed {
+					Event::ButtonPressed(index, button)
+				} else {
+					Event::ButtonReleased(index, button)
+				};
+				due.push(TimedEvent { event: event, time: time });
+			}
+		}
+		due.sort_by_key(|event| event.time);
+		out.extend(due);
+	}
+	fn next_flush(&self) -> Option<Timestamp> {
+		self.buttons.values()
+			.filter(|state| state.reported != state.pressed)
+			.map(|state| state.since.wrapping_add(self.interval))
+			.min()
+	}
+}
+
+impl Filter for DeadZones {
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		let time = event.time;
+		out.extend(self.process(event.event).into_iter().map(|event| TimedEvent { event: event, time: time }));
+	}
+}
+
+impl Filter for Curves {
+	fn filter(&mut self, event: TimedEvent, out: &mut Vec<TimedEvent>) {
+		let time = event.time;
+		out.extend(self.process(event.event).into_iter().map(|event| TimedEvent { event: event, time: time }));
+	}
+}
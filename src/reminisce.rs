@@ -17,6 +17,21 @@
 //!     println!("{:?}", event);
 //! }
 //! ```
+//!
+//! Handling errors
+//! ---------------
+//! Creating a `Backend` with `new` panics on failure and polling it with `poll`
+//! skips over errors, so use `try_new` and `try_poll` to handle them instead.
+//!
+//! ``` rust
+//! use reminisce::{Backend, Native};
+//! match Native::try_new() {
+//!     Ok(mut backend) => while let Ok(Some(event)) = backend.try_poll() {
+//!         println!("{:?}", event);
+//!     },
+//!     Err(error) => println!("Couldn't scan for joysticks: {}", error)
+//! }
+//! ```
 extern crate libc;
 #[cfg(target_os = "linux")]
 extern crate inotify;
@@ -26,6 +41,9 @@ extern crate inotify;
 #[cfg(feature = "sdl")]
 extern crate sdl2;
 
+#[cfg(feature = "async")]
+extern crate futures;
+
 #[cfg(target_os = "linux")]
 extern crate glob;
 
@@ -35,6 +53,21 @@ pub mod linux;
 #[cfg(all(target_os = "linux", not(feature = "sdl")))]
 pub use linux as native;
 
+#[cfg(target_os = "linux")]
+pub mod evdev;
+
+#[cfg(target_os = "linux")]
+mod epoll;
+
+#[cfg(target_os = "linux")]
+mod devices;
+
+#[cfg(all(target_os = "linux", not(feature = "sdl")))]
+pub mod calibration;
+
+#[cfg(all(feature = "async", target_os = "linux"))]
+pub mod stream;
+
 #[cfg(all(target_os = "windows", not(feature = "sdl")))]
 pub mod windows;
 
@@ -47,6 +80,27 @@ pub mod sdl;
 #[cfg(feature = "sdl")]
 pub use sdl as native;
 
+pub mod gamepad;
+
+pub mod mapping;
+
+pub mod deadzone;
+
+pub mod curve;
+
+pub mod pipeline;
+
+pub mod input;
+
+pub mod gesture;
+
+pub mod motion;
+
+pub mod tracker;
+
+#[doc(hidden)]
+pub mod testing;
+
 /// The native joystick backend exposed as a `Backend`.
 pub use native::Native;
 
@@ -55,7 +109,17 @@ pub use native::Native;
 const MAX_AXIS_VALUE:i16 = 32767;
 
 use std::borrow::Cow;
-use std::fmt::Debug;
+use std::cmp;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::io;
+use std::iter::FromIterator;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use curve::Curves;
+use deadzone::DeadZones;
 
 /// A direction on a joystick.
 pub type Axis = u8;
@@ -67,13 +131,71 @@ pub type Button = u8;
 pub type Hat = u8;
 
 /// A hat position on a joystick.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+///
+/// Each position is a bitmask of the directions it points in, using the same
+/// bits as SDL, so diagonals are the bits of their two directions together.
+///
+/// ``` rust
+/// use reminisce::HatPos;
+/// let pos = HatPos::from_bits(HatPos::Up as u8 | HatPos::Left as u8);
+/// assert_eq!(pos, HatPos::UpLeft);
+/// assert!(pos.contains(HatPos::Left));
+/// assert_eq!((pos.x(), pos.y()), (-1, -1));
+/// ```
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+#[repr(u8)]
 pub enum HatPos {
-    Centered,
-    Up,
-    Right,
-    Down,
-    Left
+    Centered = 0,
+    Up = 1,
+    Right = 2,
+    UpRight = 3,
+    Down = 4,
+    DownRight = 6,
+    Left = 8,
+    UpLeft = 9,
+    DownLeft = 12
+}
+
+impl HatPos {
+    /// Get the position pointing in every direction whose bit is set, with
+    /// opposite directions cancelling out
+    pub fn from_bits(bits: u8) -> HatPos {
+        let x = (bits >> 1 & 1) as i8 - (bits >> 3 & 1) as i8;
+        let y = (bits >> 2 & 1) as i8 - (bits & 1) as i8;
+        HatPos::from_xy(x, y)
+    }
+    /// Get the position pointing along `x` and `y`, where only the sign of
+    /// each matters and negative `y` is up
+    pub fn from_xy(x: i8, y: i8) -> HatPos {
+        match (x.signum(), y.signum()) {
+            (0, -1) => HatPos::Up,
+            (1, -1) => HatPos::UpRight,
+            (1, 0) => HatPos::Right,
+            (1, 1) => HatPos::DownRight,
+            (0, 1) => HatPos::Down,
+            (-1, 1) => HatPos::DownLeft,
+            (-1, 0) => HatPos::Left,
+            (-1, -1) => HatPos::UpLeft,
+            _ => HatPos::Centered
+        }
+    }
+    /// Get the bitmask of the directions this points in
+    pub fn bits(self) -> u8 {
+        self as u8
+    }
+    /// Get the horizontal part of this position, which is `-1` for left
+    pub fn x(self) -> i8 {
+        (self.bits() >> 1 & 1) as i8 - (self.bits() >> 3 & 1) as i8
+    }
+    /// Get the vertical part of this position, which is `-1` for up
+    pub fn y(self) -> i8 {
+        (self.bits() >> 2 & 1) as i8 - (self.bits() & 1) as i8
+    }
+    /// Check if this points in every direction `other` does, so `UpLeft`
+    /// contains `Up` and `Left`
+    pub fn contains(self, other: HatPos) -> bool {
+        self.bits() & other.bits() == other.bits()
+    }
 }
 
 /// A joystick index.
@@ -100,6 +222,454 @@ pub enum Event {
     HatMoved(JoystickIndex, Hat, HatPos)
 }
 
+/// A time in milliseconds.
+///
+/// Each backend stamps events using its own clock, so timestamps can only be
+/// compared with others from the same backend. They wrap around after about
+/// 49 days.
+pub type Timestamp = u32;
+
+/// An event along with the time it happened at.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimedEvent {
+    /// The event that happened
+    pub event: Event,
+    /// The time the event happened at
+    pub time: Timestamp
+}
+
+/// A set of buttons, stored as a bitset that grows to fit the highest button
+/// in it.
+///
+/// ``` rust
+/// use reminisce::ButtonSet;
+/// let mut buttons = ButtonSet::with_capacity(64);
+/// buttons.insert(3);
+/// buttons.insert(200);
+/// assert!(buttons.contains(200) && !buttons.contains(4));
+/// buttons.remove(200);
+/// assert_eq!(buttons.iter().collect::<Vec<_>>(), vec![3]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ButtonSet {
+    words: Vec<u64>
+}
+
+impl ButtonSet {
+    /// Create an empty set
+    pub fn new() -> ButtonSet {
+        ButtonSet::default()
+    }
+    /// Create an empty set with room for the number of buttons given
+    pub fn with_capacity(num_buttons: usize) -> ButtonSet {
+        ButtonSet {
+            words: vec![0; (num_buttons + 63) / 64]
+        }
+    }
+    /// Add a button to the set, returning true if it wasn't already in it
+    pub fn insert(&mut self, button: Button) -> bool {
+        let (word, bit) = (button as usize / 64, button as usize % 64);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let inserted = self.words[word] & (1 << bit) == 0;
+        self.words[word] |= 1 << bit;
+        inserted
+    }
+    /// Remove a button from the set, returning true if it was in it
+    pub fn remove(&mut self, button: Button) -> bool {
+        let (word, bit) = (button as usize / 64, button as usize % 64);
+        match self.words.get_mut(word) {
+            Some(word) if *word & (1 << bit) != 0 => {
+                *word &= !(1 << bit);
+                true
+            },
+            _ => false
+        }
+    }
+    /// Check if a button is in the set
+    pub fn contains(&self, button: Button) -> bool {
+        let (word, bit) = (button as usize / 64, button as usize % 64);
+        self.words.get(word).map_or(false, |word| word & (1 << bit) != 0)
+    }
+    /// Remove every button from the set
+    pub fn clear(&mut self) {
+        for word in &mut self.words {
+            *word = 0;
+        }
+    }
+    /// Get the number of buttons in the set
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+    /// Check if the set has no buttons in it
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+    /// Iterate through the buttons in the set, lowest first
+    pub fn iter(&self) -> ButtonSetIter {
+        ButtonSetIter {
+            set: self,
+            next: 0
+        }
+    }
+}
+
+impl PartialEq for ButtonSet {
+    /// Sets are equal if they have the same buttons, however much room they
+    /// have
+    fn eq(&self, other: &ButtonSet) -> bool {
+        let len = cmp::max(self.words.len(), other.words.len());
+        (0..len).all(|i| self.words.get(i).cloned().unwrap_or(0) == other.words.get(i).cloned().unwrap_or(0))
+    }
+}
+
+impl Eq for ButtonSet {}
+
+impl FromIterator<Button> for ButtonSet {
+    fn from_iter<I>(buttons: I) -> ButtonSet where I: IntoIterator<Item = Button> {
+        let mut set = ButtonSet::new();
+        for button in buttons {
+            set.insert(button);
+        }
+        set
+    }
+}
+
+/// An iterator over the buttons in a `ButtonSet`.
+pub struct ButtonSetIter<'a> {
+    set: &'a ButtonSet,
+    next: usize
+}
+
+impl<'a> Iterator for ButtonSetIter<'a> {
+    type Item = Button;
+    fn next(&mut self) -> Option<Button> {
+        // Buttons stop at 255, however much room the set has
+        while self.next < cmp::min(self.set.words.len() * 64, Button::max_value() as usize + 1) {
+            let button = self.next;
+            self.next += 1;
+            if self.set.contains(button as Button) {
+                return Some(button as Button)
+            }
+        }
+        None
+    }
+}
+
+/// The state of every control on a joystick at a point in time.
+///
+/// This is plain data, so it can be copied out of a `StatefulJoystick` with
+/// `snapshot` and sent to another thread or compared with another state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JoystickState {
+    /// Whether each button is pressed
+    pub buttons: Vec<bool>,
+    /// The position of each axis, between `-1` and `1`
+    pub axes: Vec<f32>,
+    /// The position of each hat
+    pub hats: Vec<HatPos>,
+    /// The time this was the state at, on the same clock as the backend's
+    /// events, or `0` if it isn't known
+    pub time: Timestamp
+}
+
+impl JoystickState {
+    /// Create the state of a joystick with the number of controls given at
+    /// rest, with no buttons pressed and every axis and hat centered.
+    pub fn new(num_buttons: Button, num_axes: Axis, num_hats: Hat) -> JoystickState {
+        JoystickState {
+            buttons: vec![false; num_buttons as usize],
+            axes: vec![0.0; num_axes as usize],
+            hats: vec![HatPos::Centered; num_hats as usize],
+            time: 0
+        }
+    }
+    /// Update this state with an event from its joystick, growing it to fit
+    /// controls it didn't know about.
+    ///
+    /// ``` rust
+    /// use reminisce::{Event, HatPos, JoystickState};
+    /// let mut state = JoystickState::new(2, 0, 0);
+    /// state.process(Event::ButtonPressed(0, 1));
+    /// state.process(Event::HatMoved(0, 0, HatPos::Up));
+    /// assert_eq!(state.buttons, vec![false, true]);
+    /// assert_eq!(state.hats, vec![HatPos::Up]);
+    /// ```
+    pub fn process(&mut self, event: Event) {
+        match event {
+            Event::ButtonPressed(_, button) | Event::ButtonReleased(_, button) => {
+                if button as usize >= self.buttons.len() {
+                    self.buttons.resize(button as usize + 1, false);
+                }
+                self.buttons[button as usize] = match event {
+                    Event::ButtonPressed(_, _) => true,
+                    _ => false
+                };
+            },
+            Event::AxisMoved(_, axis, value) => {
+                if axis as usize >= self.axes.len() {
+                    self.axes.resize(axis as usize + 1, 0.0);
+                }
+                self.axes[axis as usize] = value;
+            },
+            Event::HatMoved(_, hat, pos) => {
+                if hat as usize >= self.hats.len() {
+                    self.hats.resize(hat as usize + 1, HatPos::Centered);
+                }
+                self.hats[hat as usize] = pos;
+            },
+            Event::Connected(_) | Event::Disconnected(_) => ()
+        }
+    }
+    /// Get the events that would bring the joystick with the index given from
+    /// this state into the state `to`.
+    ///
+    /// Controls missing from either state are treated as being at rest.
+    ///
+    /// ``` rust
+    /// use reminisce::{Event, HatPos, JoystickState};
+    /// let mut from = JoystickState::new(2, 1, 1);
+    /// from.buttons[0] = true;
+    /// let mut to = from.clone();
+    /// to.buttons[0] = false;
+    /// to.axes[0] = 0.5;
+    /// to.hats[0] = HatPos::DownLeft;
+    /// assert_eq!(from.diff(&to, 3), vec![
+    ///     Event::ButtonReleased(3, 0),
+    ///     Event::AxisMoved(3, 0, 0.5),
+    ///     Event::HatMoved(3, 0, HatPos::DownLeft)
+    /// ]);
+    /// ```
+    pub fn diff(&self, to: &JoystickState, index: JoystickIndex) -> Vec<Event> {
+        let mut events = Vec::new();
+        for button in 0..cmp::max(self.buttons.len(), to.buttons.len()) {
+            let pressed = |state: &JoystickState| state.buttons.get(button).cloned().unwrap_or(false);
+            match (pressed(self), pressed(to)) {
+                (false, true) => events.push(Event::ButtonPressed(index, button as Button)),
+                (true, false) => events.push(Event::ButtonReleased(index, button as Button)),
+                _ => ()
+            }
+        }
+        for axis in 0..cmp::max(self.axes.len(), to.axes.len()) {
+            let value = |state: &JoystickState| state.axes.get(axis).cloned().unwrap_or(0.0);
+            if value(self) != value(to) {
+                events.push(Event::AxisMoved(index, axis as Axis, value(to)));
+            }
+        }
+        for hat in 0..cmp::max(self.hats.len(), to.hats.len()) {
+            let pos = |state: &JoystickState| state.hats.get(hat).cloned().unwrap_or(HatPos::Centered);
+            if pos(self) != pos(to) {
+                events.push(Event::HatMoved(index, hat as Hat, pos(to)));
+            }
+        }
+        events
+    }
+    /// Get the events that would bring the joystick with the index given from
+    /// rest into this state.
+    ///
+    /// ``` rust
+    /// use reminisce::{Event, JoystickState};
+    /// let mut state = JoystickState::new(4, 2, 0);
+    /// state.buttons[2] = true;
+    /// state.axes[1] = -1.0;
+    /// assert_eq!(state.events(0), vec![
+    ///     Event::ButtonPressed(0, 2),
+    ///     Event::AxisMoved(0, 1, -1.0)
+    /// ]);
+    /// ```
+    pub fn events(&self, index: JoystickIndex) -> Vec<Event> {
+        JoystickState::new(0, 0, 0).diff(self, index)
+    }
+}
+
+/// An axis on a joystick and the range of raw values it reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AxisInfo {
+    /// The kernel's code for this axis, like `ABS_X` (`0x00`), if known
+    pub code: Option<u16>,
+    /// The lowest value the axis reports before it is scaled to `-1`
+    pub min: i32,
+    /// The highest value the axis reports before it is scaled to `1`
+    pub max: i32
+}
+
+/// A button on a joystick.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ButtonInfo {
+    /// The kernel's code for this button, like `BTN_SOUTH` (`0x130`), if known
+    pub code: Option<u16>
+}
+
+/// A description of every control on a joystick and what else it supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The joystick's axes, in axis order
+    pub axes: Vec<AxisInfo>,
+    /// The joystick's buttons, in button order
+    pub buttons: Vec<ButtonInfo>,
+    /// The number of hats the joystick has
+    pub hats: Hat,
+    /// Whether the joystick supports force feedback
+    pub force_feedback: bool,
+    /// Whether the joystick's battery level can be read
+    pub battery: bool
+}
+
+/// An error that occurred while creating or polling a `Backend`.
+#[derive(Debug)]
+pub enum BackendError {
+    /// The operating system reported an error.
+    Io(io::Error),
+    /// The library the backend is built on reported an error.
+    Library(String),
+    /// The joystick with the index given sent an event that couldn't be
+    /// understood.
+    UnknownEvent(JoystickIndex)
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackendError::Io(ref error) => write!(f, "{}", error),
+            BackendError::Library(ref error) => write!(f, "{}", error),
+            BackendError::UnknownEvent(index) =>
+                write!(f, "joystick {} sent an unknown event", index)
+        }
+    }
+}
+
+impl Error for BackendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            BackendError::Io(ref error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for BackendError {
+    fn from(error: io::Error) -> BackendError {
+        BackendError::Io(error)
+    }
+}
+
+/// A 128-bit joystick GUID, compatible with the ones SDL uses for
+/// `SDL_GameControllerDB` mappings.
+///
+/// This is written as 32 lowercase hex digits.
+///
+/// ``` rust
+/// use reminisce::Guid;
+/// let guid: Guid = "030000005e0400008e02000014010000".parse().unwrap();
+/// assert_eq!(guid.to_string(), "030000005e0400008e02000014010000");
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            try!(write!(f, "{:02x}", byte));
+        }
+        Ok(())
+    }
+}
+
+/// An error from parsing a `Guid` that isn't 32 hex digits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseGuidError;
+
+impl fmt::Display for ParseGuidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a GUID must be 32 hex digits")
+    }
+}
+
+impl Error for ParseGuidError {}
+
+impl FromStr for Guid {
+    type Err = ParseGuidError;
+    fn from_str(text: &str) -> Result<Guid, ParseGuidError> {
+        if text.len() != 32 || !text.is_ascii() {
+            return Err(ParseGuidError)
+        }
+        let mut guid = [0; 16];
+        for (i, byte) in guid.iter_mut().enumerate() {
+            *byte = try!(u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).map_err(|_| ParseGuidError));
+        }
+        Ok(Guid(guid))
+    }
+}
+
+/// The identity of a model of joystick, which stays the same between
+/// identical joysticks and between drivers.
+///
+/// This is suitable for keying saved bindings by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceId {
+    /// The type of bus the joystick is connected through, like `BUS_USB`
+    /// (`0x03`) or `BUS_BLUETOOTH` (`0x05`)
+    pub bus: u16,
+    /// The vendor ID, or 0 if unknown
+    pub vendor: u16,
+    /// The product ID, or 0 if unknown
+    pub product: u16,
+    /// The version of the product, or 0 if unknown
+    pub version: u16,
+    /// The GUID SDL would give this joystick
+    pub guid: Guid
+}
+
+impl DeviceId {
+    /// Identify a joystick by its bus, vendor, product and version, building
+    /// its GUID the way SDL does.
+    ///
+    /// Joysticks with no vendor or product ID are told apart by the first 11
+    /// bytes of their name instead.
+    ///
+    /// ``` rust
+    /// use reminisce::DeviceId;
+    /// let id = DeviceId::new(0x03, 0x045e, 0x028e, 0x0114, "Microsoft X-Box 360 pad");
+    /// assert_eq!(id.guid.to_string(), "030000005e0400008e02000014010000");
+    /// ```
+    pub fn new(bus: u16, vendor: u16, product: u16, version: u16, name: &str) -> DeviceId {
+        let mut guid = [0; 16];
+        guid[0..2].copy_from_slice(&bus.to_le_bytes());
+        if vendor != 0 && product != 0 {
+            guid[4..6].copy_from_slice(&vendor.to_le_bytes());
+            guid[8..10].copy_from_slice(&product.to_le_bytes());
+            guid[12..14].copy_from_slice(&version.to_le_bytes());
+        } else {
+            for (byte, &c) in guid[4..15].iter_mut().zip(name.as_bytes()) {
+                *byte = c;
+            }
+        }
+        DeviceId {
+            bus: bus,
+            vendor: vendor,
+            product: product,
+            version: version,
+            guid: Guid(guid)
+        }
+    }
+    /// Recover the identity of a joystick from its GUID.
+    ///
+    /// The vendor, product and version are left as 0 if the GUID was built
+    /// from a name.
+    pub fn from_guid(guid: Guid) -> DeviceId {
+        let word = |i: usize| u16::from_le_bytes([guid.0[i], guid.0[i + 1]]);
+        let from_ids = word(6) == 0 && word(10) == 0;
+        DeviceId {
+            bus: word(0),
+            vendor: if from_ids { word(4) } else { 0 },
+            product: if from_ids { word(8) } else { 0 },
+            version: if from_ids { word(12) } else { 0 },
+            guid: guid
+        }
+    }
+}
+
 /// A lightweight Backend that tracks and polls all the available joysticks.
 ///
 /// Each `Backend` has its own event queue tied to the events of the joysticks.
@@ -126,7 +696,16 @@ pub trait Backend: Sized {
     type Joystick : Joystick;
 
     /// Create a new Backend and scan for joysticks.
-    fn new() -> Self;
+    ///
+    /// This panics if the backend couldn't be created, so use `try_new` to
+    /// handle that instead.
+    fn new() -> Self {
+        Self::try_new().unwrap()
+    }
+
+    /// Create a new Backend and scan for joysticks, or return the reason it
+    /// couldn't be created.
+    fn try_new() -> Result<Self, BackendError>;
 
     /// Returns the number of joysticks connected.
     fn num_joysticks(&self) -> usize {
@@ -137,7 +716,86 @@ pub trait Backend: Sized {
     fn joysticks(&self) -> &[Self::Joystick];
 
     /// Poll this Backend non-blockingly for events from any joysticks.
-    fn poll(&mut self) -> Option<Event>;
+    fn poll(&mut self) -> Option<Event> {
+        self.poll_timed().map(|event| event.event)
+    }
+
+    /// Poll this Backend non-blockingly for events from any joysticks, along
+    /// with the time they happened at.
+    ///
+    /// Errors are treated as there being no event, so use `try_poll_timed`
+    /// to handle them instead.
+    fn poll_timed(&mut self) -> Option<TimedEvent> {
+        self.try_poll_timed().unwrap_or(None)
+    }
+
+    /// Poll this Backend non-blockingly for events from any joysticks, or
+    /// return the error that stopped it from polling.
+    ///
+    /// If a joystick fails it is treated as disconnected, so polling again
+    /// will return its `Event::Disconnected`.
+    fn try_poll(&mut self) -> Result<Option<Event>, BackendError> {
+        self.try_poll_timed().map(|event| event.map(|event| event.event))
+    }
+
+    /// Like `try_poll`, but also returns the time events happened at.
+    fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError>;
+
+    /// Block until an event arrives from any joystick.
+    ///
+    /// Returns none if polling or waiting failed, so use `try_wait` to find
+    /// out why.
+    fn wait(&mut self) -> Option<Event> {
+        self.try_wait().ok()
+    }
+
+    /// Block until an event arrives from any joystick, or return the error
+    /// that stopped it from polling or waiting.
+    fn try_wait(&mut self) -> Result<Event, BackendError> {
+        loop {
+            if let Some(event) = try!(self.try_wait_timed(None)) {
+                return Ok(event.event)
+            }
+        }
+    }
+
+    /// Block until an event arrives from any joystick or the timeout given
+    /// elapses, returning `None` if it timed out or failed.
+    fn wait_timeout(&mut self, timeout: Duration) -> Option<Event> {
+        self.wait_timed(Some(timeout)).map(|event| event.event)
+    }
+
+    /// Block until an event arrives from any joystick or the timeout given,
+    /// if any, elapses and return it along with the time it happened at.
+    ///
+    /// Errors are treated as there being no event, so use `try_wait_timed`
+    /// to handle them instead.
+    fn wait_timed(&mut self, timeout: Option<Duration>) -> Option<TimedEvent> {
+        self.try_wait_timed(timeout).unwrap_or(None)
+    }
+
+    /// Like `wait_timed`, but returns the error that stopped it from polling
+    /// or waiting.
+    ///
+    /// By default this polls every millisecond, but backends that can sleep
+    /// on the operating system's event sources do so instead.
+    fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(event) = try!(self.try_poll_timed()) {
+                return Ok(Some(event))
+            }
+            let mut pause = Duration::from_millis(1);
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok(None)
+                }
+                pause = cmp::min(deadline - now, pause);
+            }
+            thread::sleep(pause);
+        }
+    }
 
     /// Iterate through the events that haven't been processed yet
     fn iter(&mut self) -> Poller<Self> {
@@ -184,14 +842,19 @@ pub trait Joystick: Sized {
     /// Get the index of this joystick.
     fn index(&self) -> JoystickIndex;
 
-    /// Get the number of axes this joystick has
+    /// Get the identity of this joystick's model
     ///
-    /// This is capped at 6 axes for now.
+    /// Unlike `id`, this stays the same between identical joysticks and
+    /// between drivers. Returns none if this operation is not supported by
+    /// the backend or the device.
+    fn device_id(&self) -> Option<DeviceId> {
+        None
+    }
+
+    /// Get the number of axes this joystick has
     fn num_axes(&self) -> Axis;
 
     /// Get the number of buttons this joystick has
-    ///
-    /// This is capped at 16 buttons currently.
     fn num_buttons(&self) -> Button;
 
     /// Get the number of hats this joystick has
@@ -202,22 +865,148 @@ pub trait Joystick: Sized {
     /// Returns none if the joystick is wired or this operation is not supported
     /// by the backend
     fn battery(&self) -> Option<f32>;
+
+    /// Get the state the joystick's controls were in when it was opened
+    ///
+    /// Returns none if this operation is not supported by the backend
+    fn initial_state(&self) -> Option<JoystickState> {
+        None
+    }
+
+    /// Get a description of each of this joystick's controls
+    ///
+    /// Backends that don't know the kernel codes of controls or the raw
+    /// ranges of axes leave the codes empty and report every axis as ranging
+    /// from `-32767` to `32767`.
+    ///
+    /// ``` rust
+    /// use reminisce::{Backend, Joystick, Native};
+    /// if let Ok(joystick) = <Native as Backend>::Joystick::open(0) {
+    ///     for (axis, info) in joystick.capabilities().axes.iter().enumerate() {
+    ///         println!("Axis {} has code {:?}", axis, info.code)
+    ///     }
+    /// }
+    /// ```
+    fn capabilities(&self) -> Capabilities {
+        let axis = AxisInfo {
+            code: None,
+            min: -(MAX_AXIS_VALUE as i32),
+            max: MAX_AXIS_VALUE as i32
+        };
+        Capabilities {
+            axes: vec![axis; self.num_axes() as usize],
+            buttons: vec![ButtonInfo { code: None }; self.num_buttons() as usize],
+            hats: self.num_hats(),
+            force_feedback: false,
+            battery: self.battery().is_some()
+        }
+    }
 }
 
 /// A joystick that tracks its state.
 ///
-/// You must call `process` before you query anything.
+/// This starts from the joystick's initial state if the backend reports it,
+/// and must be kept up to date by calling `process` with each event.
 pub struct StatefulJoystick<J> where J: Joystick {
     joystick: J,
-    buttons: i32,
+    buttons: ButtonSet,
     axes: Vec<f32>,
-    hats: Vec<HatPos>
+    hats: Vec<HatPos>,
+    /// The time of the last event processed with its time
+    time: Timestamp,
+    dead_zones: DeadZones,
+    curves: Curves,
+    /// The raw axes at the start of the frame
+    frame_axes: Vec<f32>,
+    /// The number of times each button was pressed this frame
+    presses: HashMap<Button, u32>,
+    /// The number of times each button was released this frame
+    releases: HashMap<Button, u32>
 }
 impl<J> StatefulJoystick<J> where J: Joystick {
-    /// Get the position of the axis.
+    /// Track the state of a joystick that is already open.
+    ///
+    /// ``` rust
+    /// use reminisce::{Event, HatPos, StatefulJoystick};
+    /// # use reminisce::testing::FakeJoystick;
+    ///
+    /// // A button box with 120 buttons and no state of its own
+    /// # let button_box = FakeJoystick::new(0, 120, 0, 0);
+    /// let mut joystick = StatefulJoystick::new(button_box);
+    /// joystick.process(Event::ButtonPressed(0, 100));
+    /// joystick.process(Event::ButtonPressed(0, 2));
+    /// joystick.process(Event::ButtonReleased(0, 2));
+    /// assert_eq!(joystick.button(100), Some(true));
+    /// assert_eq!(joystick.button(2), Some(false));
+    /// assert_eq!(joystick.button(120), None);
+    /// // Axes and hats it did not report are added as they move
+    /// joystick.process(Event::AxisMoved(0, 3, 0.5));
+    /// joystick.process(Event::HatMoved(0, 1, HatPos::Up));
+    /// assert_eq!(joystick.raw_axis(3), Some(0.5));
+    /// assert_eq!(joystick.hat(1), Some(HatPos::Up));
+    /// ```
+    pub fn new(joystick: J) -> StatefulJoystick<J> {
+        let state = joystick.initial_state().unwrap_or_else(||
+            JoystickState::new(joystick.num_buttons(), joystick.num_axes(), joystick.num_hats())
+        );
+        let mut buttons = ButtonSet::with_capacity(joystick.num_buttons() as usize);
+        for (button, _) in state.buttons.iter().enumerate().filter(|&(_, &pressed)| pressed) {
+            buttons.insert(button as Button);
+        }
+        StatefulJoystick {
+            joystick: joystick,
+            buttons: buttons,
+            hats: state.hats,
+            time: state.time,
+            frame_axes: state.axes.clone(),
+            axes: state.axes,
+            dead_zones: DeadZones::new(),
+            curves: Curves::new(),
+            presses: HashMap::new(),
+            releases: HashMap::new()
+        }
+    }
+    /// Get the position of the axis, after applying any dead zones and then
+    /// any response curves.
     pub fn axis(&self, axis: Axis) -> Option<f32> {
+        self.axes.get(axis as usize).map(|_| self.processed_axis(&self.axes, axis))
+    }
+    /// Get the position of an axis in `axes` after applying any dead zones
+    /// and then any response curves
+    fn processed_axis(&self, axes: &[f32], axis: Axis) -> f32 {
+        let index = self.joystick.index();
+        let raw = |axis: Axis| axes.get(axis as usize).cloned().unwrap_or(0.0);
+        let zoned = |axis: Axis| self.dead_zones.apply(index, axis, raw(axis), raw);
+        self.curves.apply(index, axis, zoned(axis), zoned)
+    }
+    /// Get the position of the axis as reported by the joystick.
+    pub fn raw_axis(&self, axis: Axis) -> Option<f32> {
         self.axes.get(axis as usize).cloned()
     }
+    /// Apply the dead zones given when querying axes.
+    ///
+    /// ``` rust
+    /// use reminisce::{Backend, Joystick, Native, StatefulJoystick};
+    /// use reminisce::deadzone::{DeadZone, DeadZones};
+    /// if let Ok(mut joystick) = StatefulJoystick::<<Native as Backend>::Joystick>::open(0) {
+    ///     let mut zones = DeadZones::new();
+    ///     zones.set_pair(None, 0, 1, DeadZone::scaled_radial(0.15));
+    ///     joystick.set_dead_zones(zones);
+    ///     println!("{:?}", joystick.axis(0))
+    /// }
+    /// ```
+    pub fn set_dead_zones(&mut self, dead_zones: DeadZones) {
+        self.dead_zones = dead_zones;
+    }
+    /// Apply the response curves given when querying axes, after the dead
+    /// zones.
+    pub fn set_curves(&mut self, curves: Curves) {
+        self.curves = curves;
+    }
+    /// Get the buttons that are being pressed.
+    pub fn buttons(&self) -> &ButtonSet {
+        &self.buttons
+    }
     /// Get the position of the hat.
     pub fn hat(&self, hat: Hat) -> Option<HatPos> {
         self.hats.get(hat as usize).cloned()
@@ -225,25 +1014,106 @@ impl<J> StatefulJoystick<J> where J: Joystick {
     /// Check if the button given is being pressed.
     pub fn button(&self, button: Button) -> Option<bool> {
         if button < self.joystick.num_buttons() {
-            Some(self.buttons & (1 << button) > 0)
+            Some(self.buttons.contains(button))
         } else {
             None
         }
     }
+    /// Start a new frame, forgetting the button presses and releases and
+    /// the axis movements of the last one.
+    ///
+    /// ``` rust
+    /// use reminisce::{Event, StatefulJoystick};
+    /// # use reminisce::testing::FakeJoystick;
+    ///
+    /// // A pad with 4 buttons, 2 axes and no state of its own
+    /// # let pad = FakeJoystick::new(0, 4, 2, 0);
+    /// let mut joystick = StatefulJoystick::new(pad);
+    /// joystick.process(Event::AxisMoved(0, 0, 0.25));
+    ///
+    /// joystick.begin_frame();
+    /// // A tap that starts and ends between frames is still seen
+    /// joystick.process(Event::ButtonPressed(0, 1));
+    /// joystick.process(Event::ButtonReleased(0, 1));
+    /// joystick.process(Event::ButtonPressed(0, 1));
+    /// joystick.process(Event::AxisMoved(0, 0, 1.0));
+    /// assert!(joystick.just_pressed(1) && joystick.just_released(1));
+    /// assert_eq!(joystick.press_count(1), 2);
+    /// assert_eq!(joystick.release_count(1), 1);
+    /// assert_eq!(joystick.axis_delta(0), Some(0.75));
+    ///
+    /// joystick.begin_frame();
+    /// assert!(!joystick.just_pressed(1) && joystick.button(1) == Some(true));
+    /// assert_eq!(joystick.axis_delta(0), Some(0.0));
+    /// ```
+    pub fn begin_frame(&mut self) {
+        self.presses.clear();
+        self.releases.clear();
+        self.frame_axes.clone_from(&self.axes);
+    }
+    /// Check if the button was pressed since the start of the frame.
+    pub fn just_pressed(&self, button: Button) -> bool {
+        self.press_count(button) > 0
+    }
+    /// Check if the button was released since the start of the frame.
+    pub fn just_released(&self, button: Button) -> bool {
+        self.release_count(button) > 0
+    }
+    /// Get the number of times the button was pressed since the start of the
+    /// frame.
+    pub fn press_count(&self, button: Button) -> u32 {
+        self.presses.get(&button).cloned().unwrap_or(0)
+    }
+    /// Get the number of times the button was released since the start of
+    /// the frame.
+    pub fn release_count(&self, button: Button) -> u32 {
+        self.releases.get(&button).cloned().unwrap_or(0)
+    }
+    /// Get how far the axis has moved since the start of the frame, after
+    /// applying any dead zones and response curves.
+    pub fn axis_delta(&self, axis: Axis) -> Option<f32> {
+        self.axis(axis).map(|value| value - self.processed_axis(&self.frame_axes, axis))
+    }
+    /// Copy out the state of every control, with the raw positions of axes.
+    ///
+    /// Snapshots taken at different times can be compared with
+    /// `JoystickState::diff`.
+    pub fn snapshot(&self) -> JoystickState {
+        JoystickState {
+            buttons: (0..self.joystick.num_buttons()).map(|button| self.buttons.contains(button)).collect(),
+            axes: self.axes.clone(),
+            hats: self.hats.clone(),
+            time: self.time
+        }
+    }
+    /// Update this joystick's state with the event given, and the time of
+    /// its snapshots with the time of the event.
+    pub fn process_timed(&mut self, event: TimedEvent) {
+        self.time = event.time;
+        self.process(event.event);
+    }
     /// Update this joystick's state with the event given.
     pub fn process(&mut self, event: Event) {
         let index = self.joystick.index();
         match event {
             Event::ButtonPressed(i, b) if i == index => {
-                self.buttons |= (i as i32) << b;
+                self.buttons.insert(b);
+                *self.presses.entry(b).or_insert(0) += 1;
             },
             Event::ButtonReleased(i, b) if i == index => {
-                self.buttons &= !((i as i32) << b);
+                self.buttons.remove(b);
+                *self.releases.entry(b).or_insert(0) += 1;
             },
             Event::AxisMoved(i, a, v) if i == index => {
+                if a as usize >= self.axes.len() {
+                    self.axes.resize(a as usize + 1, 0.0);
+                }
                 self.axes[a as usize] = v;
             },
             Event::HatMoved(i, h, v) if i == index => {
+                if h as usize >= self.hats.len() {
+                    self.hats.resize(h as usize + 1, HatPos::Centered);
+                }
                 self.hats[h as usize] = v;
             },
             _ => ()
@@ -253,15 +1123,7 @@ impl<J> StatefulJoystick<J> where J: Joystick {
 impl<J> Joystick for StatefulJoystick<J> where J: Joystick {
     type OpenError = J::OpenError;
     fn open(index: JoystickIndex) -> Result<Self, Self::OpenError> {
-        let joystick = try!(J::open(index));
-        let axes = (0..joystick.num_axes()).map(|_| 0f32).collect();
-        let hats = (0..joystick.num_hats()).map(|_| HatPos::Centered).collect();
-        Ok(StatefulJoystick {
-            joystick: joystick,
-            buttons: 0,
-            hats: hats,
-            axes: axes
-        })
+        J::open(index).map(StatefulJoystick::new)
     }
     fn connected(&self) -> bool {
         self.joystick.connected()
@@ -284,6 +1146,15 @@ impl<J> Joystick for StatefulJoystick<J> where J: Joystick {
     fn battery(&self) -> Option<f32> {
         self.joystick.battery()
     }
+    fn initial_state(&self) -> Option<JoystickState> {
+        self.joystick.initial_state()
+    }
+    fn capabilities(&self) -> Capabilities {
+        self.joystick.capabilities()
+    }
+    fn device_id(&self) -> Option<DeviceId> {
+        self.joystick.device_id()
+    }
 }
 
 /// An iterator over a Backend's event queue.
@@ -1,10 +1,10 @@
 use sdl2::joystick::*;
-use sdl2::{init, event, Sdl, JoystickSubsystem, ErrorMessage};
+use sdl2::{init, event, Sdl, JoystickSubsystem};
 
 use std::borrow::Cow;
-use std::mem;
+use std::time::{Duration, Instant};
 
-use {Backend, Event};
+use {Backend, BackendError, DeviceId, Event, Guid, JoystickState, TimedEvent};
 
 pub struct Native {
     sdl: Sdl,
@@ -13,14 +13,14 @@ pub struct Native {
 }
 impl Backend for Native {
     type Joystick = NativeJoystick;
-    fn new() -> Self {
-        let sdl = init().unwrap();
-        let system = sdl.joystick().unwrap();
-        Native {
+    fn try_new() -> Result<Self, BackendError> {
+        let sdl = try!(init().map_err(BackendError::Library));
+        let system = try!(sdl.joystick().map_err(BackendError::Library));
+        Ok(Native {
             sdl: sdl,
             system: system,
             joysticks: Vec::new()
-        }
+        })
     }
     fn num_joysticks(&self) -> usize {
         self.system.num_joysticks().unwrap_or(0) as usize
@@ -28,26 +28,69 @@ impl Backend for Native {
     fn joysticks(&self) -> &[NativeJoystick] {
         &self.joysticks
     }
-    fn poll(&mut self) -> Option<Event> {
-        self.sdl.event_pump().unwrap().poll_iter().filter_map(|e| match e {
-            event::Event::JoyDeviceAdded { which , ..} => {
-                self.joysticks.push(self.system.open(which as u32).unwrap());
-                Some(Event::Connected(which as ::JoystickIndex))
+    fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+        let mut pump = try!(self.sdl.event_pump().map_err(BackendError::Library));
+        Ok(pump.poll_iter().filter_map(|e| self.translate(e)).next())
+    }
+    fn try_wait_timed(&mut self, timeout: Option<Duration>) -> Result<Option<TimedEvent>, BackendError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut pump = try!(self.sdl.event_pump().map_err(BackendError::Library));
+        loop {
+            if let Some(event) = pump.poll_iter().filter_map(|e| self.translate(e)).next() {
+                return Ok(Some(event))
+            }
+            let event = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None)
+                    }
+                    let left = deadline - now;
+                    let millis = left.as_secs() * 1000 + (left.subsec_nanos() as u64 + 999_999) / 1_000_000;
+                    pump.wait_event_timeout(if millis > u32::max_value() as u64 { u32::max_value() } else { millis as u32 })
+                },
+                None => Some(pump.wait_event())
+            };
+            if let Some(event) = event.and_then(|e| self.translate(e)) {
+                return Ok(Some(event))
+            }
+        }
+    }
+}
+impl Native {
+    /// Convert an SDL event into an `Event`, opening and closing joysticks as
+    /// they are added and removed
+    fn translate(&mut self, e: event::Event) -> Option<TimedEvent> {
+        let (event, timestamp) = match e {
+            event::Event::JoyDeviceAdded { timestamp, which } => {
+                match self.system.open(which as u32) {
+                    Ok(joystick) => self.joysticks.push(joystick),
+                    Err(_) => return None
+                }
+                (Event::Connected(which as ::JoystickIndex), timestamp)
             },
-            event::Event::JoyDeviceRemoved { which, .. } => {
-                self.joysticks.remove(which as usize);
-                Some(Event::Disconnected(which as ::JoystickIndex))
+            event::Event::JoyDeviceRemoved { timestamp, which } => {
+                let index = which as ::JoystickIndex;
+                match self.joysticks.iter().position(|js| ::Joystick::index(js) == index) {
+                    Some(arr_index) => { self.joysticks.remove(arr_index); },
+                    None => return None
+                }
+                (Event::Disconnected(index), timestamp)
             },
-            event::Event::JoyButtonDown { which, button_idx, .. } =>
-                Some(Event::ButtonPressed(which as ::JoystickIndex, button_idx)),
-            event::Event::JoyButtonUp { which, button_idx, .. } =>
-                Some(Event::ButtonReleased(which as ::JoystickIndex, button_idx)),
-            event::Event::JoyAxisMotion { which, axis_idx, value , .. } =>
-                Some(Event::AxisMoved(which as ::JoystickIndex, axis_idx, value as f32 / ::MAX_AXIS_VALUE as f32)),
-            event::Event::JoyHatMotion { which, hat_idx, state, .. } =>
-                Some(Event::HatMoved(which as ::JoystickIndex, hat_idx, unsafe { mem::transmute(state) })),
-            _ => None,
-        }).next()
+            event::Event::JoyButtonDown { timestamp, which, button_idx } =>
+                (Event::ButtonPressed(which as ::JoystickIndex, button_idx), timestamp),
+            event::Event::JoyButtonUp { timestamp, which, button_idx } =>
+                (Event::ButtonReleased(which as ::JoystickIndex, button_idx), timestamp),
+            event::Event::JoyAxisMotion { timestamp, which, axis_idx, value } =>
+                (Event::AxisMoved(which as ::JoystickIndex, axis_idx, value as f32 / ::MAX_AXIS_VALUE as f32), timestamp),
+            event::Event::JoyHatMotion { timestamp, which, hat_idx, state } =>
+                (Event::HatMoved(which as ::JoystickIndex, hat_idx, ::HatPos::from_bits(state.to_raw())), timestamp),
+            _ => return None,
+        };
+        Some(TimedEvent {
+            event: event,
+            time: timestamp
+        })
     }
 }
 
@@ -56,9 +99,11 @@ impl Backend for Native {
 pub type NativeJoystick = Joystick;
 
 impl ::Joystick for NativeJoystick {
-    type OpenError = ErrorMessage;
-    fn open(index: u8) -> Result<NativeJoystick, ErrorMessage> {
-        init().unwrap().joystick().unwrap().open(index as u32)
+    type OpenError = BackendError;
+    fn open(index: u8) -> Result<NativeJoystick, BackendError> {
+        let sdl = try!(init().map_err(BackendError::Library));
+        let system = try!(sdl.joystick().map_err(BackendError::Library));
+        system.open(index as u32).map_err(|error| BackendError::Library(error.to_string()))
     }
     fn connected(&self) -> bool {
         self.attached()
@@ -69,6 +114,9 @@ impl ::Joystick for NativeJoystick {
     fn id(&self) -> Cow<str> {
         self.name().into()
     }
+    fn device_id(&self) -> Option<DeviceId> {
+        Some(DeviceId::from_guid(Guid(self.guid().raw().data)))
+    }
     fn num_buttons(&self) -> u8 {
         self.num_buttons() as u8
     }
@@ -81,4 +129,16 @@ impl ::Joystick for NativeJoystick {
     fn battery(&self) -> Option<f32> {
         None
     }
+    fn initial_state(&self) -> Option<JoystickState> {
+        Some(JoystickState {
+            buttons: (0..self.num_buttons()).map(|i| self.button(i).unwrap_or(false)).collect(),
+            axes: (0..self.num_axes()).map(|i| self.axis(i).unwrap_or(0) as f32 / ::MAX_AXIS_VALUE as f32).collect(),
+            hats: (0..self.num_hats()).map(|i| match self.hat(i) {
+                Ok(state) => ::HatPos::from_bits(state.to_raw()),
+                Err(_) => ::HatPos::Centered
+            }).collect(),
+            // Events are stamped with the same clock
+            time: unsafe { ::sdl2::sys::SDL_GetTicks() }
+        })
+    }
 }
\ No newline at end of file
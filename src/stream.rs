@@ -0,0 +1,155 @@
+use futures::stream::Stream;
+use futures::task::{Context, Poll, Waker};
+use libc::{c_int, c_void, eventfd, poll, pollfd, write, close, EFD_CLOEXEC, EINTR, POLLIN};
+use std::io::Error;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use {Backend, Event};
+
+/// An endless `Stream` of the events from a `Backend`, for use in async
+/// applications.
+///
+/// Rather than polling the backend repeatedly, this sleeps on its file
+/// descriptor in a helper thread and wakes the task once it becomes readable.
+///
+/// ``` rust
+/// # extern crate futures;
+/// # extern crate reminisce;
+/// use futures::executor::block_on;
+/// use futures::StreamExt;
+/// use reminisce::{Backend, Event};
+/// use reminisce::evdev::Native;
+/// use reminisce::stream::EventStream;
+/// # use std::fs::{self, File};
+/// # use std::io::Write;
+/// # use std::mem;
+///
+/// # fn main() {
+/// # let root = std::env::temp_dir().join("reminisce-event-stream");
+/// # fs::create_dir_all(&root).unwrap();
+/// # let mut record = vec![0u8; 2 * mem::size_of::<usize>()];
+/// # record.extend(&1u16.to_ne_bytes());
+/// # record.extend(&0x120u16.to_ne_bytes());
+/// # record.extend(&1i32.to_ne_bytes());
+/// # File::create(root.join("event0")).unwrap().write_all(&record).unwrap();
+/// let mut events = EventStream::new(Native::with_root(&root).unwrap());
+/// assert_eq!(block_on(events.next()), Some(Event::Connected(0)));
+/// assert_eq!(block_on(events.next()), Some(Event::ButtonPressed(0, 0)));
+/// # drop(events);
+/// # fs::remove_dir_all(&root).unwrap();
+/// # }
+/// ```
+pub struct EventStream<B> where B: Backend + AsRawFd {
+	// This is declared first so the helper thread stops before the backend
+	// closes its file descriptor
+	waiter: Option<Waiter>,
+	backend: B
+}
+
+impl<B> EventStream<B> where B: Backend + AsRawFd {
+	/// Create a new stream of the events from the backend given
+	pub fn new(backend: B) -> EventStream<B> {
+		EventStream {
+			waiter: None,
+			backend: backend
+		}
+	}
+	/// Get a reference to the backend
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+	/// Get a mutable reference to the backend
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+	/// Stop streaming and return the backend
+	pub fn into_inner(self) -> B {
+		let EventStream { waiter, backend } = self;
+		drop(waiter);
+		backend
+	}
+}
+
+impl<B> Stream for EventStream<B> where B: Backend + AsRawFd + Unpin {
+	type Item = Event;
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
+		let this = self.get_mut();
+		if let Some(event) = this.backend.poll() {
+			return Poll::Ready(Some(event))
+		}
+		if this.waiter.is_none() {
+			this.waiter = Waiter::new(this.backend.as_raw_fd()).ok();
+		}
+		match this.waiter {
+			Some(ref waiter) => waiter.wait(cx.waker().clone()),
+			// Without a helper thread the best that can be done is polling again
+			None => cx.waker().wake_by_ref()
+		}
+		Poll::Pending
+	}
+}
+
+/// A helper thread that wakes tasks once a file descriptor is readable
+struct Waiter {
+	wakers: Option<Sender<Waker>>,
+	/// An eventfd that is written to when the thread should stop
+	cancel: c_int,
+	thread: Option<JoinHandle<()>>
+}
+
+impl Waiter {
+	fn new(fd: RawFd) -> Result<Waiter, Error> {
+		let cancel = unsafe { eventfd(0, EFD_CLOEXEC) };
+		if cancel == -1 {
+			return Err(Error::last_os_error())
+		}
+		let (sender, receiver) = channel::<Waker>();
+		let thread = thread::spawn(move || {
+			for waker in receiver.iter() {
+				let mut fds = [
+					pollfd { fd: fd, events: POLLIN, revents: 0 },
+					pollfd { fd: cancel, events: POLLIN, revents: 0 }
+				];
+				while unsafe { poll(fds.as_mut_ptr(), 2, -1) } == -1 {
+					if Error::last_os_error().raw_os_error() != Some(EINTR) {
+						break
+					}
+				}
+				if fds[1].revents != 0 {
+					return
+				}
+				waker.wake();
+			}
+		});
+		Ok(Waiter {
+			wakers: Some(sender),
+			cancel: cancel,
+			thread: Some(thread)
+		})
+	}
+	/// Wake the task given once the file descriptor is readable
+	fn wait(&self, waker: Waker) {
+		if let Some(ref wakers) = self.wakers {
+			let _ = wakers.send(waker);
+		}
+	}
+}
+
+impl Drop for Waiter {
+	fn drop(&mut self) {
+		let one: u64 = 1;
+		unsafe {
+			write(self.cancel, &one as *const u64 as *const c_void, mem::size_of::<u64>());
+		}
+		self.wakers.take();
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+		unsafe {
+			close(self.cancel);
+		}
+	}
+}
@@ -0,0 +1,85 @@
+//! A joystick and a backend that replay scripted events, so examples can run
+//! without any hardware.
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use {Axis, Backend, BackendError, Button, Event, Hat, Joystick, JoystickIndex, TimedEvent, Timestamp};
+
+/// A joystick with the controls given and no state of its own
+pub struct FakeJoystick {
+	index: JoystickIndex,
+	buttons: Button,
+	axes: Axis,
+	hats: Hat
+}
+
+impl FakeJoystick {
+	pub fn new(index: JoystickIndex, buttons: Button, axes: Axis, hats: Hat) -> FakeJoystick {
+		FakeJoystick {
+			index: index,
+			buttons: buttons,
+			axes: axes,
+			hats: hats
+		}
+	}
+}
+
+impl Joystick for FakeJoystick {
+	type OpenError = ();
+	fn open(index: JoystickIndex) -> Result<FakeJoystick, ()> {
+		Ok(FakeJoystick::new(index, 0, 0, 0))
+	}
+	fn connected(&self) -> bool {
+		true
+	}
+	fn id(&self) -> Cow<str> {
+		"Fake joystick".into()
+	}
+	fn index(&self) -> JoystickIndex {
+		self.index
+	}
+	fn num_axes(&self) -> Axis {
+		self.axes
+	}
+	fn num_buttons(&self) -> Button {
+		self.buttons
+	}
+	fn num_hats(&self) -> Hat {
+		self.hats
+	}
+	fn battery(&self) -> Option<f32> {
+		None
+	}
+}
+
+/// A backend that returns the events pushed onto it, in order
+pub struct Script {
+	joysticks: Vec<FakeJoystick>,
+	events: VecDeque<TimedEvent>
+}
+
+impl Script {
+	/// Create a script where the joysticks given can be connected
+	pub fn new(joysticks: Vec<FakeJoystick>) -> Script {
+		Script {
+			joysticks: joysticks,
+			events: VecDeque::new()
+		}
+	}
+	/// Queue an event that happened at the time given
+	pub fn push(&mut self, event: Event, time: Timestamp) {
+		self.events.push_back(TimedEvent { event: event, time: time });
+	}
+}
+
+impl Backend for Script {
+	type Joystick = FakeJoystick;
+	fn try_new() -> Result<Script, BackendError> {
+		Ok(Script::new(Vec::new()))
+	}
+	fn joysticks(&self) -> &[FakeJoystick] {
+		&self.joysticks
+	}
+	fn try_poll_timed(&mut self) -> Result<Option<TimedEvent>, BackendError> {
+		Ok(self.events.pop_front())
+	}
+}
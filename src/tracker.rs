@@ -0,0 +1,124 @@
+//! The state of every connected joystick, kept up to date from a `Backend`.
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use {Backend, Event, Joystick, JoystickIndex, JoystickState, TimedEvent};
+
+/// Tracks the state of every joystick a backend reports, adding and removing
+/// joysticks as they are connected and disconnected.
+///
+/// Joysticks start from the state they were in when they were opened if the
+/// backend reports it.
+///
+/// ``` rust
+/// use reminisce::{Event, TimedEvent};
+/// use reminisce::tracker::StateTracker;
+/// # use reminisce::testing::{FakeJoystick, Script};
+///
+/// // A backend that replays events from a joystick with 2 buttons and an axis
+/// # let backend = Script::new(vec![FakeJoystick::new(0, 2, 1, 0)]);
+/// let mut tracker = StateTracker::new(backend);
+/// tracker.backend_mut().push(Event::Connected(0), 5);
+/// tracker.backend_mut().push(Event::ButtonPressed(0, 1), 10);
+/// tracker.backend_mut().push(Event::AxisMoved(0, 0, 0.5), 20);
+/// tracker.update();
+/// {
+///     let state = tracker.state(0).unwrap();
+///     assert_eq!(state.buttons, vec![false, true]);
+///     assert_eq!(state.axes, vec![0.5]);
+///     assert_eq!(state.time, 20);
+/// }
+/// // Events for joysticks that are not connected are ignored
+/// tracker.process(TimedEvent { event: Event::ButtonPressed(1, 0), time: 30 });
+/// assert_eq!(tracker.states().count(), 1);
+///
+/// tracker.backend_mut().push(Event::Disconnected(0), 40);
+/// tracker.update();
+/// assert!(tracker.state(0).is_none());
+/// ```
+pub struct StateTracker<B> where B: Backend {
+	backend: B,
+	states: BTreeMap<JoystickIndex, JoystickState>
+}
+
+impl<B> StateTracker<B> where B: Backend {
+	/// Track the joysticks of the backend given
+	pub fn new(backend: B) -> StateTracker<B> {
+		StateTracker {
+			backend: backend,
+			states: BTreeMap::new()
+		}
+	}
+	/// Get a reference to the backend
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+	/// Get a mutable reference to the backend
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+	/// Stop tracking and return the backend
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+	/// Get the state of the connected joystick with the index given
+	pub fn state(&self, index: JoystickIndex) -> Option<&JoystickState> {
+		self.states.get(&index)
+	}
+	/// Iterate through the state of every connected joystick, in order of
+	/// index
+	pub fn states(&self) -> States {
+		States { states: self.states.iter() }
+	}
+	/// Update the states with an event, which becomes the time of the
+	/// joystick's state
+	pub fn process(&mut self, event: TimedEvent) {
+		match event.event {
+			Event::Connected(index) => {
+				let mut state = self.backend.joysticks().iter()
+					.find(|js| js.index() == index)
+					.map(|js| js.initial_state().unwrap_or_else(||
+						JoystickState::new(js.num_buttons(), js.num_axes(), js.num_hats())
+					))
+					.unwrap_or_else(|| JoystickState::new(0, 0, 0));
+				if state.time == 0 {
+					state.time = event.time;
+				}
+				self.states.insert(index, state);
+			},
+			Event::Disconnected(index) => {
+				self.states.remove(&index);
+			},
+			Event::ButtonPressed(index, _) | Event::ButtonReleased(index, _) |
+			Event::AxisMoved(index, _, _) | Event::HatMoved(index, _, _) => {
+				if let Some(state) = self.states.get_mut(&index) {
+					state.process(event.event);
+					state.time = event.time;
+				}
+			}
+		}
+	}
+	/// Poll for the next event, updating the states with it
+	pub fn poll(&mut self) -> Option<TimedEvent> {
+		let event = self.backend.poll_timed();
+		if let Some(event) = event {
+			self.process(event);
+		}
+		event
+	}
+	/// Update the states with every event that is ready
+	pub fn update(&mut self) {
+		while self.poll().is_some() {}
+	}
+}
+
+/// An iterator over the states of the joysticks a `StateTracker` tracks.
+pub struct States<'a> {
+	states: btree_map::Iter<'a, JoystickIndex, JoystickState>
+}
+
+impl<'a> Iterator for States<'a> {
+	type Item = (JoystickIndex, &'a JoystickState);
+	fn next(&mut self) -> Option<(JoystickIndex, &'a JoystickState)> {
+		self.states.next().map(|(&index, state)| (index, state))
+	}
+}
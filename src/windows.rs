@@ -158,29 +158,25 @@ impl ::Joystick for NativeJoystick {
 		self
 	}
 }
-macro_rules! event{
-	(button $this:expr, $now:expr, $last:expr, $btn:expr, $id:expr) => (
-		{
-			let now_btn = $now.buttons.contains($btn);
-			let last_btn = $last.buttons.contains($btn);
-			if now_btn && !last_btn {
-				$this.events.push_back(::Event::ButtonPressed($id))
-			} else if !now_btn && last_btn {
-				$this.events.push_back(::Event::ButtonReleased($id))
-			}
-		}
-	);
-	(axis $this:expr, $now:expr, $last:expr, $field:ident, $id:expr) => (
-		if $now.$field != $last.$field {
-			$this.events.push_back(::Event::AxisMoved($id, $now.$field))
+/// The buttons in the order they are numbered
+const BUTTONS: [Buttons; 10] = [A, B, X, Y, LEFT_SHOULDER, RIGHT_SHOULDER, BACK, START, LEFT_THUMB, RIGHT_THUMB];
+
+impl Gamepad {
+	/// Convert this into the state of every control, with the D-pad as a hat
+	fn state(&self, time: ::Timestamp) -> ::JoystickState {
+		let axis = |value: i16| value as f32 / ::MAX_AXIS_VALUE as f32;
+		let direction = |negative, positive| match (self.buttons.contains(negative), self.buttons.contains(positive)) {
+			(true, false) => -1,
+			(false, true) => 1,
+			_ => 0
+		};
+		::JoystickState {
+			buttons: BUTTONS.iter().map(|&button| self.buttons.contains(button)).collect(),
+			axes: vec![axis(self.thumb_lx), axis(self.thumb_ly), axis(self.thumb_rx), axis(self.thumb_ry)],
+			hats: vec![::HatPos::from_xy(direction(DPAD_LEFT, DPAD_RIGHT), direction(DPAD_UP, DPAD_DOWN))],
+			time: time
 		}
-	);
-	(axes $this:expr, $now:expr, $last:expr, $($field:ident => $id:expr),+) => ({
-		$(event!(axis $this, $now, $last, $field, $id);)+
-	});
-	(buttons $this:expr, $now:expr, $last:expr, $($btn:ident => $id:expr),+) => ({
-		$(event!(button $this, $now, $last, $btn, $id);)+
-	});
+	}
 }
 impl ::StatefulJoystick for NativeJoystick {
 	fn axis(&self, index: ::Axis) -> Option<i16> {
@@ -216,25 +212,8 @@ impl ::StatefulJoystick for NativeJoystick {
 		};
 		let now = state.gamepad;
 		if state.packet != self.last_packet {
-			let last = self.last;
-			event!{axes self, now, last,
-				thumb_lx => ::Axis::LeftX,
-				thumb_ly => ::Axis::LeftY,
-				thumb_rx => ::Axis::RightX,
-				thumb_ry => ::Axis::RightY
-			}
-			event!{buttons self, now, last,
-				A => ::Button::A,
-				B => ::Button::B,
-				X => ::Button::X,
-				Y => ::Button::Y,
-				LEFT_SHOULDER => ::Button::LeftShoulder,
-				RIGHT_SHOULDER => ::Button::RightShoulder,
-				BACK => ::Button::Select,
-				START => ::Button::Start,
-				LEFT_THUMB => ::Button::LeftStick,
-				RIGHT_THUMB => ::Button::RightStick
-			}
+			let events = self.last.state(0).diff(&now.state(0), self.index);
+			self.events.extend(events);
 		}
 		self.last_packet = state.packet;
 		self.last = now;